@@ -4,7 +4,6 @@ create table base (
     c varchar,
     d datetime not null 
 ) cluster by (a, b);
-create table rand like base Engine = Random;
 create table sink like base;
 
 alter table base set options(change_tracking=true);
@@ -1,22 +1,22 @@
 INSERT FIRST
     WHEN n % 10 = 0 THEN
-      INTO t0
+      INTO ${DATABASE}.t0
     WHEN n % 10 = 1 THEN
-      INTO t1
+      INTO ${DATABASE}.t1
     WHEN n % 10 = 2 THEN
-        INTO t2
+        INTO ${DATABASE}.t2
     WHEN n % 10 = 3 THEN
-        INTO t3
+        INTO ${DATABASE}.t3
     WHEN n % 10 = 4 THEN
-        INTO t4
+        INTO ${DATABASE}.t4
     WHEN n % 10 = 5 THEN
-        INTO t5
+        INTO ${DATABASE}.t5
     WHEN n % 10 = 6 THEN
-        INTO t6
+        INTO ${DATABASE}.t6
     WHEN n % 10 = 7 THEN
-        INTO t7
+        INTO ${DATABASE}.t7
     WHEN n % 10 = 8 THEN
-        INTO t8
+        INTO ${DATABASE}.t8
     WHEN n % 10 = 9 THEN
-        INTO t9
+        INTO ${DATABASE}.t9
 SELECT number as n from numbers(10000);
\ No newline at end of file
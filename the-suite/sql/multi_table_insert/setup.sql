@@ -1,10 +1,10 @@
-create or replace table t0(c int) cluster by (c);
-create or replace table t1(c int) cluster by (c);
-create or replace table t2(c int) cluster by (c);
-create or replace table t3(c int) cluster by (c);
-create or replace table t4(c int) cluster by (c);
-create or replace table t5(c int) cluster by (c);
-create or replace table t6(c int) cluster by (c);
-create or replace table t7(c int) cluster by (c);
-create or replace table t8(c int) cluster by (c);
-create or replace table t9(c int) cluster by (c);
+create or replace table ${DATABASE}.t0(c int) cluster by (c);
+create or replace table ${DATABASE}.t1(c int) cluster by (c);
+create or replace table ${DATABASE}.t2(c int) cluster by (c);
+create or replace table ${DATABASE}.t3(c int) cluster by (c);
+create or replace table ${DATABASE}.t4(c int) cluster by (c);
+create or replace table ${DATABASE}.t5(c int) cluster by (c);
+create or replace table ${DATABASE}.t6(c int) cluster by (c);
+create or replace table ${DATABASE}.t7(c int) cluster by (c);
+create or replace table ${DATABASE}.t8(c int) cluster by (c);
+create or replace table ${DATABASE}.t9(c int) cluster by (c);
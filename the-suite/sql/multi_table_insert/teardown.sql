@@ -1,19 +1,19 @@
-(SELECT 't0' as table_name, count(*) FROM fuse_snapshot('default', 't0'))
+(SELECT 't0' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't0'))
 UNION ALL
-(SELECT 't1' as table_name, count(*) FROM fuse_snapshot('default', 't1'))
+(SELECT 't1' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't1'))
 UNION ALL
-(SELECT 't2' as table_name, count(*) FROM fuse_snapshot('default', 't2'))
+(SELECT 't2' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't2'))
 UNION ALL
-(SELECT 't3' as table_name, count(*) FROM fuse_snapshot('default', 't3'))
+(SELECT 't3' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't3'))
 UNION ALL
-(SELECT 't4' as table_name, count(*) FROM fuse_snapshot('default', 't4'))
+(SELECT 't4' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't4'))
 UNION ALL
-(SELECT 't5' as table_name, count(*) FROM fuse_snapshot('default', 't5'))
+(SELECT 't5' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't5'))
 UNION ALL
-(SELECT 't6' as table_name, count(*) FROM fuse_snapshot('default', 't6'))
+(SELECT 't6' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't6'))
 UNION ALL
-(SELECT 't7' as table_name, count(*) FROM fuse_snapshot('default', 't7'))
+(SELECT 't7' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't7'))
 UNION ALL
-(SELECT 't8' as table_name, count(*) FROM fuse_snapshot('default', 't8'))
+(SELECT 't8' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't8'))
 UNION ALL
-(SELECT 't9' as table_name, count(*) FROM fuse_snapshot('default', 't9'));
+(SELECT 't9' as table_name, count(*) FROM fuse_snapshot('${DATABASE}', 't9'));
@@ -0,0 +1,37 @@
+CREATE
+OR REPLACE TABLE ${DATABASE}.json_table(create_at TIMESTAMP, v VARIANT);
+
+CREATE
+OR REPLACE STREAM ${DATABASE}.json_table_stream ON TABLE ${DATABASE}.json_table APPEND_ONLY = true;
+
+COPY INTO ${DATABASE}.json_table
+FROM
+    @s_temp PATTERN = '.*[.]csv' FILE_FORMAT = (TYPE = CSV SKIP_HEADER = 1);
+
+CREATE
+OR REPLACE TABLE ${DATABASE}.json_table_flag_1(
+    id BIGINT,
+    create_at TIMESTAMP,
+    v VARIANT
+);
+
+CREATE
+OR REPLACE TABLE ${DATABASE}.json_table_flag_2(
+    id BIGINT,
+    create_at TIMESTAMP,
+    v VARIANT
+);
+
+CREATE
+OR REPLACE TABLE ${DATABASE}.json_table_flag_3(
+    id BIGINT,
+    create_at TIMESTAMP,
+    v VARIANT
+);
+
+CREATE
+OR REPLACE TABLE ${DATABASE}.json_table_flag_4(
+    id BIGINT,
+    create_at TIMESTAMP,
+    v VARIANT
+);
\ No newline at end of file
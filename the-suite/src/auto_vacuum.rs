@@ -1,14 +1,22 @@
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use clap::Parser;
-use databend_driver::{Client, Connection};
+use databend_driver::Connection;
 use log::info;
 use tokio::task::JoinHandle;
 
+use crate::load::{Load, LoadArgs};
+use crate::nodes::Role;
+use crate::preflight::Requirement;
+use crate::suite::{Suite, SuiteContext};
+use crate::util::ConnectionExt;
+
 /// Auto Vacuum Testing Script - Tests for table corruption with small DATA_RETENTION_NUM_SNAPSHOTS_TO_KEEP values
 /// - See issue: https://github.com/databendlabs/databend/issues/18006
-/// - This case should fail in databend version https://github.com/databendlabs/databend/releases/tag/v1.2.743-nightlyhhhhhhhhh
+/// - The table gets corrupted on https://github.com/databendlabs/databend/releases/tag/v1.2.743-nightly
+/// - Skipped on the servers without auto vacuum, see `REQUIREMENTS`
 #[derive(Parser, Clone, Debug)]
 pub struct Args {
     /// Number of concurrent insertion threads
@@ -23,41 +31,37 @@ pub struct Args {
     #[arg(long, default_value_t = 10)]
     insert_batch_size: u32,
 
+    #[command(flatten)]
+    load: LoadArgs,
 }
 
 #[derive(Clone)]
 pub struct AutoVacuumSuite {
     args: Args,
-    dsn: String,
+    ctx: SuiteContext,
+    database: String,
 }
 
 impl AutoVacuumSuite {
-    fn new(args: Args, dsn: String) -> Self {
-        Self { args, dsn }
-    }
-
     async fn new_setup_connection(&self) -> Result<Box<dyn Connection>> {
-        let client = Client::new(self.dsn.clone());
-        let conn = client.get_conn().await?;
-        Ok(conn)
+        self.ctx.new_connection().await
     }
 
     async fn new_connection(&self) -> Result<Box<dyn Connection>> {
         let conn = self.new_setup_connection().await?;
-        conn.exec("set enable_auto_vacuum=1").await?;
-        conn.exec("use auto_vacuum").await?;
+        conn.exec(&format!("use {}", self.database)).await?;
         Ok(conn)
     }
 
-    async fn setup(&self) -> Result<()> {
+    async fn create_tables(&self) -> Result<()> {
         info!("===== Running setup for auto vacuum test =====");
 
         let conn = self.new_setup_connection().await?;
 
         // Create test table with small DATA_RETENTION_NUM_SNAPSHOTS_TO_KEEP
         let setup_sqls = [
-            "create or replace database auto_vacuum",
-            "use auto_vacuum",
+            format!("create or replace database {}", self.database),
+            format!("use {}", self.database),
             "CREATE OR REPLACE TABLE test (
                 id DECIMAL(38, 0) NOT NULL,
                 a VARIANT NULL,
@@ -71,29 +75,41 @@ impl AutoVacuumSuite {
             ) CLUSTER BY linear(id)
               BLOCK_SIZE_THRESHOLD='419430400'
               COMPRESSION='zstd'
-              DATA_RETENTION_NUM_SNAPSHOTS_TO_KEEP='3'",
-            "CREATE OR REPLACE TABLE r LIKE test ENGINE = random",
+              DATA_RETENTION_NUM_SNAPSHOTS_TO_KEEP='3'"
+                .to_owned(),
         ];
 
         for sql in setup_sqls {
             info!("Executing setup SQL: {}", sql);
-            conn.exec(sql).await?;
+            conn.exec(&sql).await?;
         }
 
         info!("===== Setup completed =====");
         Ok(())
     }
 
-    async fn execute_insert(&self, batch_id: u32) -> Result<()> {
-        let conn = self.new_connection().await?;
-        let sql = format!(
-            "INSERT INTO test SELECT * FROM r LIMIT {}",
-            self.args.insert_batch_size
-        );
+    async fn execute_insert(&self, batch_id: u32, load: Load) -> Result<()> {
+        let conn = self.ctx.new_connection_as(Role::Writer).await?;
+        conn.exec(&format!("use {}", self.database)).await?;
+        let mut gen = self.ctx.datagen(batch_id as u64);
 
-        for i in 0..self.args.inserts_per_iteration {
-            info!("\n===== Batch {batch_id} Iteration {i} Progress {}% =====", i * 100 / self.args.inserts_per_iteration);
-            match conn.exec(&sql).await {
+        let mut pacer = load.pacer(Some(self.args.inserts_per_iteration as u64));
+        while let Some(scheduled) = pacer.next().await {
+            info!(
+                "\n===== Batch {batch_id} Iteration {} Progress {}% =====",
+                pacer.done(),
+                pacer.progress()
+            );
+            let sql = format!(
+                "INSERT INTO test VALUES {}",
+                gen.vacuum_rows(self.args.insert_batch_size as usize)
+            );
+            let result = self
+                .ctx
+                .retry_policy()
+                .run_from(&self.ctx.stats(), "insert", scheduled, || conn.exec(&sql))
+                .await;
+            match result {
                 Ok(_) => {
                     info!("INSERT completed successfully");
                 }
@@ -126,10 +142,12 @@ impl AutoVacuumSuite {
 
     async fn run_concurrent_inserts(&self) -> Result<Vec<JoinHandle<Result<()>>>> {
         let mut handles = Vec::new();
+        let load = self.args.load.start(&self.ctx.shutdown());
 
         for i in 0..self.args.concurrency {
             let self_clone = Arc::new(self.clone());
-            let handle = tokio::spawn(async move { self_clone.execute_insert(i).await });
+            let load = load.clone();
+            let handle = tokio::spawn(async move { self_clone.execute_insert(i, load).await });
             handles.push(handle);
         }
 
@@ -142,23 +160,166 @@ impl AutoVacuumSuite {
         }
         Ok(())
     }
+}
+
+#[async_trait]
+impl Suite for AutoVacuumSuite {
+    type Args = Args;
+    const NAME: &'static str = "auto-vacuum";
+    const DESCRIPTION: &'static str =
+        "Concurrent inserts with auto vacuum and a small DATA_RETENTION_NUM_SNAPSHOTS_TO_KEEP";
+    const SETTINGS: &'static [(&'static str, &'static str)] = &[("enable_auto_vacuum", "1")];
+    const REQUIREMENTS: &'static [Requirement] = &[Requirement::Setting("enable_auto_vacuum")];
+    const CRASH_RECOVERY: bool = true;
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("auto_vacuum");
+        Self {
+            args,
+            ctx,
+            database,
+        }
+    }
 
-    pub async fn run(args: Args, dsn: String) -> Result<()> {
-        let suite = Self::new(args, dsn);
-        suite.setup().await?;
+    async fn setup(&self) -> Result<()> {
+        self.create_tables().await
+    }
 
+    async fn execute(&self) -> Result<()> {
         // Run concurrent inserts
-        let handles = suite.run_concurrent_inserts().await?;
-        suite.wait_for_completion(handles).await?;
+        let handles = self.run_concurrent_inserts().await?;
+        self.wait_for_completion(handles).await
+    }
+
+    async fn verify(&self) -> Result<()> {
         // Check table health
-        if !suite.check_table_health().await? {
+        if !self.check_table_health().await? {
             return Err(anyhow!("Table health check failed. Test terminated."));
         }
 
         Ok(())
     }
+
+    async fn committed_rows(&self) -> Result<u64> {
+        if !self.check_table_health().await? {
+            return Err(anyhow!("Table health check failed"));
+        }
+        let conn = self.new_connection().await?;
+        let rows: Vec<(u64,)> = conn.exec_query("SELECT count(*) FROM test").await?;
+        Ok(rows.first().map_or(0, |row| row.0))
+    }
 }
 
-pub async fn run(args: Args, dsn: String) -> Result<()> {
-    AutoVacuumSuite::run(args, dsn).await
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::{MockServer, Reply};
+    use crate::suite::run_suite;
+
+    fn args() -> Args {
+        Args {
+            concurrency: 3,
+            inserts_per_iteration: 5,
+            insert_batch_size: 10,
+            load: LoadArgs::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn inserts_with_auto_vacuum_enabled() {
+        let mock = MockServer::new();
+        let ctx = mock
+            .context()
+            .with_default_settings(AutoVacuumSuite::SETTINGS);
+        run_suite(AutoVacuumSuite::new(args(), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        assert_eq!(mock.count("INSERT INTO test VALUES"), 15);
+        assert_eq!(ctx.stats().snapshot()["insert"].success, 15);
+        // setup, 3 writers, the health check and the drop of the database
+        assert_eq!(mock.count("SET enable_auto_vacuum = 1"), 6);
+        assert_eq!(mock.connections(), 6);
+        let database = ctx.database("auto_vacuum");
+        assert_eq!(mock.count(&format!("use {database}")), 5);
+        assert_eq!(
+            mock.count(&format!("DROP DATABASE IF EXISTS {database}")),
+            1
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn kept_database_is_not_dropped() {
+        let mock = MockServer::new();
+        let ctx = mock.context().with_keep(true);
+        run_suite(AutoVacuumSuite::new(args(), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        assert_eq!(mock.count("DROP DATABASE"), 0);
+        // another run creates a database of its own
+        let other = ctx.for_new_run();
+        assert_ne!(other.database("auto_vacuum"), ctx.database("auto_vacuum"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn inserts_at_a_rate_until_the_deadline() {
+        let mock = MockServer::new();
+        let args = Args {
+            load: LoadArgs {
+                duration: Some(Duration::from_millis(500)),
+                rate: Some(20.0),
+            },
+            ..args()
+        };
+        let ctx = mock.context();
+        run_suite(AutoVacuumSuite::new(args, ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        // the iterations are ignored, up to 10 inserts of each of the 3 writers
+        let inserts = mock.count("INSERT INTO test VALUES");
+        assert!(inserts > 15 && inserts <= 30, "{inserts} inserts");
+        assert_eq!(
+            ctx.stats().latencies(Duration::from_millis(500))["insert"].count,
+            inserts as u64
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_inserts_are_counted() {
+        let mock = MockServer::new();
+        mock.when("INSERT INTO test", Reply::error(4001, "conflict").times(4));
+        let ctx = mock.context();
+        run_suite(AutoVacuumSuite::new(args(), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let inserts = &ctx.stats().snapshot()["insert"];
+        assert_eq!((inserts.success, inserts.failed), (11, 4));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn corrupted_table_fails_the_suite() {
+        let mock = MockServer::new();
+        mock.when("ignore_result", Reply::error(1001, "storage error"));
+        let ctx = mock.context();
+        let result = run_suite(AutoVacuumSuite::new(args(), ctx.clone()), &ctx).await;
+
+        assert!(result.is_err());
+        assert_eq!(mock.count("DROP DATABASE"), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_run_is_kept_on_failure() {
+        let mock = MockServer::new();
+        mock.when("ignore_result", Reply::error(1001, "storage error"));
+        let ctx = mock.context().with_keep_on_failure(true);
+        let result = run_suite(AutoVacuumSuite::new(args(), ctx.clone()), &ctx).await;
+
+        assert!(result.is_err());
+        assert_eq!(mock.count("DROP DATABASE"), 0);
+    }
 }
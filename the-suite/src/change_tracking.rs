@@ -1,19 +1,34 @@
-use std::fs::read_to_string;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use anyhow::anyhow;
 use anyhow::Result;
+use async_trait::async_trait;
 use clap::Parser;
-use databend_driver::{Client, Connection};
+use databend_driver::Connection;
 use log::info;
 use tokio::task::JoinHandle;
 
+use crate::datagen::{values_source, BASE_COLUMNS};
+use crate::load::{Load, LoadArgs};
+use crate::nodes::Role;
+use crate::preflight::{Requirement, CHANGE_TRACKING};
+use crate::row_diff;
+use crate::suite::{Suite, SuiteContext};
+use crate::util::{read_statements, ConnectionExt};
+
 const SET_UP: &str = "./sql/change_tracking/setup.sql";
 const SET_UP_CLUSTERED: &str = "./sql/change_tracking/setup_clustered.sql";
 
+// streams of the generated rows, one per worker
+const SETUP_ROWS: u64 = 0;
+const INSERT_ROWS: u64 = 1;
+const REPLACE_ROWS: u64 = 2;
+const MERGE_ROWS: u64 = 3;
+
 /// Change Tracking Testing Script
-#[derive(Parser, Debug)]
+/// - See docs/change_tracking.md for the details of the scenario
+#[derive(Parser, Clone, Debug)]
 pub struct Args {
     /// number of derived streams
     #[arg(long, default_value_t = 5)]
@@ -38,23 +53,24 @@ pub struct Args {
     /// append only or standard stream
     #[arg(long, default_value_t = false)]
     clustered_table: bool,
+
+    /// `--duration` consumes the streams until the deadline instead of
+    /// `--times-consumption-per-stream`, `--rate` paces each of the consumers and of the workers
+    /// mutating the base table
+    #[command(flatten)]
+    load: LoadArgs,
 }
 
+#[derive(Clone)]
 pub struct ChangeTrackingSuite {
     args: Args,
     stop_flag: Arc<AtomicBool>,
-    dsn: String,
+    ctx: SuiteContext,
+    /// the `test_stream` database of the run
+    database: String,
 }
 
 impl ChangeTrackingSuite {
-    fn new(args: Args, dsn: String) -> Self {
-        Self {
-            args,
-            stop_flag: Arc::new(AtomicBool::new(false)),
-            dsn,
-        }
-    }
-
     async fn wait_stream_consuming(&self, handles: Vec<JoinHandle<Result<u32>>>) -> Result<u32> {
         let mut success = 0;
         for join_handle in handles {
@@ -67,18 +83,24 @@ impl ChangeTrackingSuite {
 
     async fn new_connection_with_test_db(&self) -> Result<Box<dyn Connection>> {
         let conn = self.new_connection().await?;
-        // set current database to 'test_stream'
-        conn.exec("use test_stream").await?;
+        // set current database to the 'test_stream' of the run
+        conn.exec(&format!("use {}", self.database)).await?;
         Ok(conn)
     }
 
     async fn new_connection(&self) -> Result<Box<dyn Connection>> {
-        let client = Client::new(self.dsn.clone());
-        let conn = client.get_conn().await?;
+        self.ctx.new_connection().await
+    }
+
+    /// A connection of a worker of `role` on the database of the run, see
+    /// [`SuiteContext::new_connection_as`]
+    async fn new_connection_as(&self, role: Role) -> Result<Box<dyn Connection>> {
+        let conn = self.ctx.new_connection_as(role).await?;
+        conn.exec(&format!("use {}", self.database)).await?;
         Ok(conn)
     }
 
-    async fn setup(&self) -> Result<()> {
+    async fn run_setup_script(&self) -> Result<()> {
         info!("=====running setup script====");
 
         let conn = self.new_connection().await?;
@@ -88,20 +110,21 @@ impl ChangeTrackingSuite {
             SET_UP
         };
         info!("setup file path {}", setup_file_path);
-        let setup_script = read_to_string(setup_file_path)?;
-
-        let db_set_sqls = vec!["create or replace database test_stream", "use test_stream"];
+        let setup_statements = read_statements(setup_file_path)?;
 
-        let setup_lines = setup_script.split(';');
+        let db_set_sqls = [
+            format!("create or replace database {}", self.database),
+            format!("use {}", self.database),
+        ];
 
-        let sqls = db_set_sqls.into_iter().chain(setup_lines);
+        for sql in db_set_sqls {
+            info!("executing sql: {}", sql);
+            conn.exec(&sql).await?;
+        }
 
-        for sql in sqls {
-            let sql = sql.trim();
-            if !sql.is_empty() {
-                info!("executing sql: {}", sql);
-                conn.exec(sql).await?;
-            }
+        for statement in &setup_statements {
+            info!("executing sql: {}", statement.sql);
+            conn.exec_statement(setup_file_path, statement).await?;
         }
 
         info!("====setup done====");
@@ -109,13 +132,23 @@ impl ChangeTrackingSuite {
         Ok(())
     }
 
-    async fn begin_insertion(&self) -> Result<JoinHandle<Result<()>>> {
-        let conn = self.new_connection_with_test_db().await?;
-        let sql = "insert into base select a, b, uuid() as c, d from rand limit 100";
+    async fn begin_insertion(&self, load: Load) -> Result<JoinHandle<Result<()>>> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+        let mut gen = self.ctx.datagen(INSERT_ROWS);
         let stop_flag = self.stop_flag.clone();
+        let stats = self.ctx.stats();
+        let policy = self.ctx.retry_policy();
         let handle = tokio::spawn(async move {
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            let mut pacer = load.pacer(None);
+            while let Some(scheduled) = pacer.next().await {
+                if stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let sql = format!("insert into base values {}", gen.base_rows(100));
+                let result = policy
+                    .run_from(&stats, "insert", scheduled, || conn.exec(&sql))
+                    .await;
+                if let Err(e) = result {
                     info!("Insertion err: {e}");
                 }
             }
@@ -125,13 +158,22 @@ impl ChangeTrackingSuite {
         Ok(handle)
     }
 
-    async fn begin_delete(&self) -> Result<JoinHandle<Result<()>>> {
-        let conn = self.new_connection_with_test_db().await?;
+    async fn begin_delete(&self, load: Load) -> Result<JoinHandle<Result<()>>> {
+        let conn = self.new_connection_as(Role::Writer).await?;
         let sql = "delete from base where a < -15000 and d < '1970-01-01 00:00:00'";
         let stop_flag = self.stop_flag.clone();
+        let stats = self.ctx.stats();
+        let policy = self.ctx.retry_policy();
         let handle = tokio::spawn(async move {
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            let mut pacer = load.pacer(None);
+            while let Some(scheduled) = pacer.next().await {
+                if stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let result = policy
+                    .run_from(&stats, "delete", scheduled, || conn.exec(sql))
+                    .await;
+                if let Err(e) = result {
                     info!("Deletion err: {e}");
                 }
             }
@@ -141,13 +183,23 @@ impl ChangeTrackingSuite {
         Ok(handle)
     }
 
-    async fn begin_replace(&self) -> Result<JoinHandle<Result<()>>> {
-        let conn = self.new_connection_with_test_db().await?;
-        let sql = "replace into base on(a) select a, b, uuid() as c, d from rand limit 2";
+    async fn begin_replace(&self, load: Load) -> Result<JoinHandle<Result<()>>> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+        let mut gen = self.ctx.datagen(REPLACE_ROWS);
         let stop_flag = self.stop_flag.clone();
+        let stats = self.ctx.stats();
+        let policy = self.ctx.retry_policy();
         let handle = tokio::spawn(async move {
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            let mut pacer = load.pacer(None);
+            while let Some(scheduled) = pacer.next().await {
+                if stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let sql = format!("replace into base on(a) values {}", gen.base_rows(2));
+                let result = policy
+                    .run_from(&stats, "replace", scheduled, || conn.exec(&sql))
+                    .await;
+                if let Err(e) = result {
                     info!("Replace err: {e}");
                 }
             }
@@ -157,13 +209,22 @@ impl ChangeTrackingSuite {
         Ok(handle)
     }
 
-    async fn begin_update(&self) -> Result<JoinHandle<Result<()>>> {
-        let conn = self.new_connection_with_test_db().await?;
+    async fn begin_update(&self, load: Load) -> Result<JoinHandle<Result<()>>> {
+        let conn = self.new_connection_as(Role::Writer).await?;
         let sql = "update base set d = now() where d > '2099-01-01 00:00:00' and a > 15000";
         let stop_flag = self.stop_flag.clone();
+        let stats = self.ctx.stats();
+        let policy = self.ctx.retry_policy();
         let handle = tokio::spawn(async move {
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            let mut pacer = load.pacer(None);
+            while let Some(scheduled) = pacer.next().await {
+                if stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let result = policy
+                    .run_from(&stats, "update", scheduled, || conn.exec(sql))
+                    .await;
+                if let Err(e) = result {
                     info!("Update err: {e}");
                 }
             }
@@ -173,15 +234,26 @@ impl ChangeTrackingSuite {
         Ok(handle)
     }
 
-    async fn begin_merge(&self) -> Result<JoinHandle<Result<()>>> {
-        let conn = self.new_connection_with_test_db().await?;
-        let sql = "merge into base using (select a, b, uuid() as c, d from rand limit 10) as s on base.a = s.a \
-                        when matched and s.d > '2099-01-01 00:00:00' then update set base.b = s.b and base.d = now() \
-                        when matched and s.d < '1970-01-01 00:00:00' then delete when not matched then insert *";
+    async fn begin_merge(&self, load: Load) -> Result<JoinHandle<Result<()>>> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+        let mut gen = self.ctx.datagen(MERGE_ROWS);
         let stop_flag = self.stop_flag.clone();
+        let stats = self.ctx.stats();
+        let policy = self.ctx.retry_policy();
         let handle = tokio::spawn(async move {
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            let mut pacer = load.pacer(None);
+            while let Some(scheduled) = pacer.next().await {
+                if stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                let source = values_source(&gen.base_rows(10), BASE_COLUMNS);
+                let sql = format!("merge into base using ({source}) as s on base.a = s.a \
+                        when matched and s.d > '2099-01-01 00:00:00' then update set base.b = s.b and base.d = now() \
+                        when matched and s.d < '1970-01-01 00:00:00' then delete when not matched then insert *");
+                let result = policy
+                    .run_from(&stats, "merge", scheduled, || conn.exec(&sql))
+                    .await;
+                if let Err(e) = result {
                     info!("Merge err: {e}");
                 }
             }
@@ -192,13 +264,17 @@ impl ChangeTrackingSuite {
     }
 
     async fn begin_compaction(&self) -> Result<JoinHandle<Result<u32>>> {
-        let conn = self.new_connection_with_test_db().await?;
+        let conn = self.new_connection_as(Role::Maintenance).await?;
         let sql = "optimize table base compact";
         let stop_flag = self.stop_flag.clone();
+        let shutdown = self.ctx.shutdown();
+        let stats = self.ctx.stats();
         let handle = tokio::spawn(async move {
             let mut success_compaction = 0;
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            // the stop flag is not set if the execution is dropped at the end of a grace period
+            while !stop_flag.load(Ordering::Relaxed) && !shutdown.is_requested() {
+                let result = stats.timed("compact", conn.exec(sql)).await;
+                if let Err(e) = result {
                     info!("table compaction err: {e}");
                 } else {
                     success_compaction += 1;
@@ -211,13 +287,17 @@ impl ChangeTrackingSuite {
     }
 
     async fn begin_recluster(&self) -> Result<JoinHandle<Result<u32>>> {
-        let conn = self.new_connection_with_test_db().await?;
+        let conn = self.new_connection_as(Role::Maintenance).await?;
         let sql = "alter table base recluster";
         let stop_flag = self.stop_flag.clone();
+        let shutdown = self.ctx.shutdown();
+        let stats = self.ctx.stats();
         let handle = tokio::spawn(async move {
             let mut success_recluster = 0;
-            while !stop_flag.load(Ordering::Relaxed) {
-                if let Err(e) = conn.exec(sql).await {
+            // the stop flag is not set if the execution is dropped at the end of a grace period
+            while !stop_flag.load(Ordering::Relaxed) && !shutdown.is_requested() {
+                let result = stats.timed("recluster", conn.exec(sql)).await;
+                if let Err(e) = result {
                     info!("table recluster err: {e}");
                 } else {
                     success_recluster += 1;
@@ -260,21 +340,25 @@ impl ChangeTrackingSuite {
         Ok(())
     }
 
-    async fn consume_derived_streams(self: &Arc<Self>) -> Result<Vec<JoinHandle<Result<u32>>>> {
+    async fn consume_derived_streams(
+        self: &Arc<Self>,
+        load: Load,
+    ) -> Result<Vec<JoinHandle<Result<u32>>>> {
         let mut handles = Vec::new();
         for idx in 0..self.args.num_derived_streams {
             let s = self.clone();
-            let join_handle = tokio::spawn(async move { s.concurrently_consume(idx).await });
+            let load = load.clone();
+            let join_handle = tokio::spawn(async move { s.concurrently_consume(idx, load).await });
             handles.push(join_handle);
         }
 
         Ok(handles)
     }
 
-    async fn concurrently_consume(&self, stream_id: u32) -> Result<u32> {
+    async fn concurrently_consume(&self, stream_id: u32, load: Load) -> Result<u32> {
         let append_only = self.args.append_only_stream;
         let sql = if append_only {
-            if stream_id % 2 == 0 {
+            if stream_id.is_multiple_of(2) {
                 format!(
                     "insert into sink_{stream_id}  select a, b, c, d from base_stream_{stream_id}"
                 )
@@ -292,16 +376,23 @@ impl ChangeTrackingSuite {
 
         let mut handles = Vec::new();
         for batch_id in 0..self.args.stream_consumption_concurrency {
-            let conn = self.new_connection_with_test_db().await?;
+            let conn = self.new_connection_as(Role::Writer).await?;
             let iters = self.args.times_consumption_per_stream;
+            let load = load.clone();
             let show_err = self.args.show_stream_consumption_errors;
+            let stats = self.ctx.stats();
             let join_handle = tokio::spawn({
                 let sql = sql.clone();
                 async move {
                     let mut sucess: u32 = 0;
-                    let step = (iters / 100).max(1);
-                    for i in 0..iters {
-                        if let Err(e) = conn.exec(&sql).await {
+                    let step = (iters as u64 / 100).max(1);
+                    let mut pacer = load.pacer(Some(iters as u64));
+                    while let Some(scheduled) = pacer.next().await {
+                        let i = pacer.done() - 1;
+                        let result = stats
+                            .timed_from("stream_consume", scheduled, conn.exec(&sql))
+                            .await;
+                        if let Err(e) = result {
                             if show_err {
                                 info!(
                                     "exec: batch {}, stream {}, iter {},  `{}` failed, {}",
@@ -316,13 +407,13 @@ impl ChangeTrackingSuite {
                             sucess += 1;
                         }
 
-                        if (i + 1) % step == 0 {
+                        if (i + 1).is_multiple_of(step) {
                             info!(
-                                "exec: batch {}, stream {}, iter {}, progress {:.2}%",
+                                "exec: batch {}, stream {}, iter {}, progress {}%",
                                 batch_id,
                                 stream_id,
                                 i,
-                                (i + 1) as f32 * 100.0 / iters as f32
+                                pacer.progress()
                             );
                         }
                     }
@@ -341,63 +432,46 @@ impl ChangeTrackingSuite {
         Ok(success)
     }
 
-    async fn verify(&self) -> Result<()> {
+    /// Compares every `sink_{idx}` with `sink` row by row, keyed on the uuid column `c`, and
+    /// checks that none of them has duplicate `c`
+    async fn check_sinks(&self) -> Result<()> {
         info!("==========================");
         info!("======verify result=======");
         info!("==========================");
         let conn = self.new_connection_with_test_db().await?;
 
-        let row = conn.query_row("select count() from sink").await?;
-        let (count,): (u64,) = row.unwrap().try_into().unwrap();
-        let row = conn.query_row("select sum(a) from sink").await?;
-        let (sum,): (u64,) = row.unwrap().try_into().unwrap();
-
-        info!("===========================");
-        info!("Sink table: row count: {count}");
-        info!("Sink table: sum of column `b`: {sum}");
-        info!("===========================");
-        info!("");
-
-        let mut diverses = Vec::new();
-
-        info!("===========================");
+        let rows: Vec<(u64,)> = conn.exec_query("select count() from sink").await?;
+        info!(
+            "Sink table: row count: {}",
+            rows.first().map_or(0, |row| row.0)
+        );
 
+        let mut failures = Vec::new();
+        let duplicates = row_diff::duplicates(&*conn, "sink", "c").await?;
+        for (c, rows) in &duplicates {
+            info!("duplicate c {c} in sink: {rows} rows");
+        }
+        if !duplicates.is_empty() {
+            failures.push(format!("sink: {} duplicate keys", duplicates.len()));
+        }
         for idx in 0..self.args.num_derived_streams {
-            let row = conn
-                .query_row(format!("select count() from sink_{idx}").as_str())
-                .await?;
-            let (c,): (u64,) = row.unwrap().try_into().unwrap();
-            let row = conn
-                .query_row(format!("select sum(a) from sink_{idx}").as_str())
-                .await?;
-            let (s,): (u64,) = row.unwrap().try_into().unwrap();
-            info!(
-                "sink of derived stream {}: row count {}, sum {} ",
-                idx, c, s
-            );
-            if count == c && sum == s {
-                continue;
-            } else {
-                diverses.push((idx, c, s));
+            let sink = format!("sink_{idx}");
+            let diff = row_diff::diff(&*conn, "sink", &sink, "c", BASE_COLUMNS).await?;
+            diff.log();
+            if !diff.is_empty() {
+                failures.push(diff.summary());
             }
         }
 
         info!("===========================");
-        info!("");
-
-        if diverses.is_empty() {
-            info!("===========================");
+        if failures.is_empty() {
             info!("======     PASSED      ====");
             info!("===========================");
             Ok(())
         } else {
-            info!("===========================");
             info!("======     FAILED      ====");
             info!("===========================");
-            for (idx, c, s) in diverses {
-                info!("diverse result in sink_{idx}: row count: {c}, sum: {s}");
-            }
-            Err(anyhow!("Test Failed"))
+            Err(anyhow!("sinks diverged: {}", failures.join("; ")))
         }
     }
 
@@ -421,33 +495,56 @@ impl ChangeTrackingSuite {
         }
         Ok(())
     }
+}
+
+#[async_trait]
+impl Suite for ChangeTrackingSuite {
+    type Args = Args;
+    const NAME: &'static str = "change-tracking";
+    const DESCRIPTION: &'static str =
+        "Concurrent stream consumption while the base table is being mutated";
+    const REQUIREMENTS: &'static [Requirement] = &[Requirement::Feature(&CHANGE_TRACKING)];
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("test_stream");
+        Self {
+            args,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            ctx,
+            database,
+        }
+    }
 
-    pub async fn run(args: Args, dsn: String) -> Result<()> {
-        info!("###options###: \n {:#?}", args);
+    async fn setup(&self) -> Result<()> {
+        self.run_setup_script().await?;
 
-        let driver = ChangeTrackingSuite::new(args, dsn);
+        // insert some generated data (this is optional)
+        let conn = self.new_connection_with_test_db().await?;
+        let sql = format!(
+            "insert into base values {}",
+            self.ctx.datagen(SETUP_ROWS).base_rows(10)
+        );
+        let _ = conn.exec(&sql).await?;
+        Ok(())
+    }
 
-        let driver = Arc::new(driver);
-        driver.setup().await?;
+    async fn execute(&self) -> Result<()> {
+        let driver = Arc::new(self.clone());
 
         let append_only = driver.args.append_only_stream;
         let clustered_base_table = driver.args.clustered_table;
 
-        // insert some random data (this is optional)
-        let conn = driver.new_connection_with_test_db().await?;
-        let sql = "insert into base select a, b, uuid() as c, d from rand limit 10";
-        let _ = conn.exec(sql).await?;
-
-        let insertion_handle = driver.begin_insertion().await?;
+        let load = driver.args.load.start(&driver.ctx.shutdown());
+        let insertion_handle = driver.begin_insertion(load.clone()).await?;
         let compaction_handle = driver.begin_compaction().await?;
-        let deletion_handle = driver.begin_delete().await?;
+        let deletion_handle = driver.begin_delete(load.clone()).await?;
         let mut update_handle = None;
         let mut merge_handle = None;
         let mut replace_handle = None;
         if !append_only {
-            update_handle = Some(driver.begin_update().await?);
-            merge_handle = Some(driver.begin_merge().await?);
-            replace_handle = Some(driver.begin_replace().await?);
+            update_handle = Some(driver.begin_update(load.clone()).await?);
+            merge_handle = Some(driver.begin_merge(load.clone()).await?);
+            replace_handle = Some(driver.begin_replace(load.clone()).await?);
         }
         let mut recluster_handle = None;
 
@@ -467,14 +564,17 @@ impl ChangeTrackingSuite {
 
         // for each derived stream, concurrently consume it
         // by inserting the change-set into sink tables
-        let handles = driver.consume_derived_streams().await?;
+        let handles = driver.consume_derived_streams(load).await?;
 
         let success = driver.wait_stream_consuming(handles).await?;
         let args = &driver.args;
 
-        let total_times_stream_consumption = args.stream_consumption_concurrency
-            * args.times_consumption_per_stream
-            * args.num_derived_streams;
+        let total_times_stream_consumption = driver
+            .ctx
+            .stats()
+            .snapshot()
+            .get("stream_consume")
+            .map_or(0, |ops| ops.attempts);
         info!("###options(recall)###: \n {:#?}", args);
         info!("==========================");
         info!(
@@ -515,10 +615,202 @@ impl ChangeTrackingSuite {
         info!("finalizing consuming all streams");
         driver.final_consume_all_streams().await?;
 
-        info!("verifing");
-        // verify
-        driver.verify().await?;
-
         Ok(())
     }
+
+    async fn verify(&self) -> Result<()> {
+        self.check_sinks().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::{string, uint, MockServer, Reply};
+    use crate::shutdown::Shutdown;
+    use crate::suite::run_suite;
+    use crate::suite::Connector;
+
+    fn args(append_only_stream: bool) -> Args {
+        Args {
+            num_derived_streams: 2,
+            stream_consumption_concurrency: 2,
+            times_consumption_per_stream: 3,
+            show_stream_consumption_errors: false,
+            append_only_stream,
+            clustered_table: false,
+            load: LoadArgs::default(),
+        }
+    }
+
+    /// The sink has `count` rows, the derived sinks have the same rows unless overridden by
+    /// earlier rules
+    fn sinks(mock: &MockServer, count: u64) {
+        mock.when(
+            "select count() from sink",
+            Reply::rows(vec![vec![uint(count)]]),
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn consumes_every_stream_and_stops_the_workers() {
+        let mock = MockServer::new();
+        sinks(&mock, 10);
+        let ctx = mock.context();
+        run_suite(ChangeTrackingSuite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let ops = ctx.stats().snapshot();
+        // 2 streams * 2 consumers * 3 times
+        assert_eq!(ops["stream_consume"].success, 12);
+        // the mutations of standard streams, without recluster as the table is not clustered
+        for kind in ["insert", "delete", "update", "merge", "replace", "compact"] {
+            assert!(ops.contains_key(kind), "no {kind} issued");
+        }
+        assert!(!ops.contains_key("recluster"));
+        // no mutation is issued once the final consumption starts
+        let statements = mock.statements();
+        let last_mutation = statements
+            .iter()
+            .rposition(|sql| sql.starts_with("insert into base") || sql.starts_with("optimize"))
+            .unwrap();
+        let final_consume = statements
+            .iter()
+            .position(|sql| sql.starts_with("merge into sink as t"))
+            .unwrap();
+        assert!(last_mutation < final_consume);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn append_only_streams_are_only_appended() {
+        let mock = MockServer::new();
+        sinks(&mock, 10);
+        let ctx = mock.context();
+        run_suite(ChangeTrackingSuite::new(args(true), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let ops = ctx.stats().snapshot();
+        for kind in ["update", "merge", "replace"] {
+            assert!(!ops.contains_key(kind), "{kind} issued");
+        }
+        assert_eq!(mock.count("append_only = true"), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_consumptions_are_counted() {
+        let mock = MockServer::new();
+        sinks(&mock, 10);
+        // all the concurrent consumptions fail, the final one succeeds
+        mock.when(
+            "from base_stream_",
+            Reply::error(2735, "conflict").times(12),
+        );
+        let ctx = mock.context();
+        run_suite(ChangeTrackingSuite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let consumptions = &ctx.stats().snapshot()["stream_consume"];
+        assert_eq!((consumptions.success, consumptions.failed), (0, 12));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn interrupted_run_verifies_the_partial_state() {
+        let mock = MockServer::new();
+        sinks(&mock, 10);
+        mock.when(
+            "from base_stream_",
+            Reply::ok().delay(Duration::from_millis(5)),
+        );
+        let args = Args {
+            load: LoadArgs {
+                duration: Some(Duration::from_secs(3600)),
+                rate: None,
+            },
+            ..args(false)
+        };
+        let ctx = mock.context();
+        let shutdown = ctx.shutdown();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            shutdown.request();
+        });
+        run_suite(ChangeTrackingSuite::new(args, ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        // the consumers stopped long before the deadline, and the sinks were still verified
+        assert!(ctx.stats().snapshot()["stream_consume"].success > 0);
+        assert_eq!(mock.count("merge into sink as t"), 1);
+        assert!(mock.count("select count() from sink") > 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn maintenance_stops_when_the_grace_period_expires() {
+        let mock = MockServer::new();
+        sinks(&mock, 10);
+        // the execution outlives the grace period and is dropped, with the compaction running
+        mock.when(
+            "create stream base_stream",
+            Reply::ok().delay(Duration::from_secs(2)),
+        );
+        let ctx = mock
+            .context()
+            .with_shutdown(Shutdown::default().with_grace(Duration::from_millis(50)));
+        let shutdown = ctx.shutdown();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            shutdown.request();
+        });
+        run_suite(ChangeTrackingSuite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let compactions = mock.count("optimize table base compact");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // one compaction may still have been in flight
+        assert!(mock.count("optimize table base compact") <= compactions + 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn workers_connect_to_the_nodes_of_their_roles() {
+        let (writer, maintenance) = (MockServer::new(), MockServer::new());
+        sinks(&writer, 10);
+        sinks(&maintenance, 10);
+        let connectors: Vec<Arc<dyn Connector>> =
+            vec![Arc::new(writer.clone()), Arc::new(maintenance.clone())];
+        let ctx = SuiteContext::with_connectors(connectors)
+            .with_assignments(&[(Role::Writer, 1), (Role::Maintenance, 2)])
+            .unwrap();
+        run_suite(ChangeTrackingSuite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        for sql in ["delete from base", "replace into base", "update base"] {
+            assert!(writer.count(sql) > 0, "no {sql} on the writer node");
+            assert_eq!(maintenance.count(sql), 0, "{sql} on the maintenance node");
+        }
+        assert!(maintenance.count("optimize table base compact") > 0);
+        assert_eq!(writer.count("optimize table base compact"), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn diverged_sink_fails_the_suite() {
+        let mock = MockServer::new();
+        let diff = Reply::rows(vec![vec![string("missing"), uint(1)]]);
+        mock.when("from sink_1) as a", diff.times(1));
+        sinks(&mock, 10);
+        let ctx = mock.context();
+        let result = run_suite(ChangeTrackingSuite::new(args(false), ctx.clone()), &ctx).await;
+
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(
+            error.contains("sink_1: 1 missing, 0 extra, 0 mismatched rows"),
+            "{error}"
+        );
+    }
 }
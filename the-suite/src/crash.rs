@@ -0,0 +1,151 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use log::{info, warn};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use tokio::sync::Mutex;
+
+use crate::launcher::LocalCluster;
+use crate::suite::Suite;
+
+/// Crashes of the databend-query nodes launched by the harness, see [`Crasher`]
+#[derive(Args, Clone, Debug)]
+pub struct CrashArgs {
+    /// Kill a databend-query node of the cluster of `--launch` with SIGKILL at random points of the
+    /// workloads, once per period on average, then restart it and check the tables of the suite
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub crash_every: Option<Duration>,
+}
+
+/// Kills and restarts the databend-query nodes of a [`LocalCluster`] while a suite executes its
+/// workload. After every restart the tables of the suite must be healthy and their committed rows
+/// must not go backwards, which a torn commit or a snapshot referencing purged data would break,
+/// see [`Suite::committed_rows`]. The crashes of a run are drawn from the seed of the run.
+pub struct Crasher {
+    cluster: Arc<Mutex<LocalCluster>>,
+    every: Duration,
+    /// How long a restarted node is waited for
+    wait: Duration,
+    seed: u64,
+    crashes: AtomicU64,
+}
+
+impl Crasher {
+    pub fn new(
+        cluster: Arc<Mutex<LocalCluster>>,
+        every: Duration,
+        wait: Duration,
+        seed: u64,
+    ) -> Self {
+        Self {
+            cluster,
+            every,
+            wait,
+            seed,
+            crashes: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of the crashes so far
+    pub fn crashes(&self) -> u64 {
+        self.crashes.load(Ordering::Relaxed)
+    }
+
+    /// Runs `workload`, the execution of `suite`, crashing the nodes in the meantime. Once it
+    /// returns, every node is running again and the tables of the suite are checked one last time
+    pub async fn execute<S: Suite, T>(
+        &self,
+        suite: &S,
+        workload: impl Future<Output = T>,
+    ) -> Result<T> {
+        let mut committed = suite
+            .committed_rows()
+            .await
+            .context("failed to count the committed rows before the crashes")?;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let output = tokio::select! {
+            output = workload => output,
+            result = self.crash_repeatedly(suite, &mut rng, &mut committed) => {
+                match result? {}
+            }
+        };
+        // the workload may have ended in the middle of a crash
+        self.cluster.lock().await.ensure_running(self.wait).await?;
+        check(suite, committed, self.crashes()).await?;
+        Ok(output)
+    }
+
+    /// Crashes a node at random points until a check fails
+    async fn crash_repeatedly<S: Suite>(
+        &self,
+        suite: &S,
+        rng: &mut ChaCha8Rng,
+        committed: &mut u64,
+    ) -> Result<Infallible> {
+        loop {
+            tokio::time::sleep(self.every.mul_f64(rng.gen_range(0.0..2.0))).await;
+            let crash = self.crashes.fetch_add(1, Ordering::Relaxed) + 1;
+            {
+                let mut cluster = self.cluster.lock().await;
+                let node = rng.gen_range(0..cluster.query_nodes());
+                warn!("crash {crash}: killing databend-query {}", node + 1);
+                cluster.kill_query(node).await?;
+                cluster
+                    .start_query(node, self.wait)
+                    .await
+                    .with_context(|| format!("failed to restart databend-query {}", node + 1))?;
+            }
+            *committed = check(suite, *committed, crash)
+                .await
+                .with_context(|| format!("after crash {crash}"))?;
+        }
+    }
+}
+
+/// Checks the tables of `suite` after `crashes`, their committed rows must be `before` at least
+async fn check<S: Suite>(suite: &S, before: u64, crashes: u64) -> Result<u64> {
+    let committed = suite.committed_rows().await?;
+    if committed < before {
+        return Err(anyhow!(
+            "committed rows went backwards from {before} to {committed}"
+        ));
+    }
+    info!(
+        "[{}] {committed} committed rows after {crashes} crashes",
+        S::NAME
+    );
+    Ok(committed)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::mock::{uint, MockServer, Reply};
+    use crate::vacuum2::{Args, Vacuum2Suite};
+
+    #[tokio::test]
+    async fn committed_rows_must_not_go_backwards() {
+        let mock = MockServer::new();
+        mock.when("SELECT count(*)", Reply::rows(vec![vec![uint(90)]]));
+        let suite = Vacuum2Suite::new(Args::parse_from(["vacuum2"]), mock.context());
+
+        assert_eq!(check(&suite, 90, 1).await.unwrap(), 90);
+        let error = check(&suite, 100, 2).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "committed rows went backwards from 100 to 90"
+        );
+
+        mock.when("ignore_result", Reply::error(1025, "missing block"));
+        let error = check(&suite, 0, 3).await.unwrap_err();
+        assert_eq!(error.to_string(), "Table health check failed");
+    }
+}
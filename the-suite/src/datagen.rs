@@ -0,0 +1,174 @@
+use std::fmt::Write as _;
+
+use chrono::DateTime;
+use rand::distributions::Alphanumeric;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Columns of the `base` table of the change tracking suite, see `sql/change_tracking/setup.sql`
+pub const BASE_COLUMNS: &str = "a, b, c, d";
+
+/// Columns of the `test_order` table of the upsert suites, see `sql/upsert/setup.sql`
+pub const ORDER_COLUMNS: &str = "id, id1, id2, id3, id4, id5, id6, id7, \
+     s1, s2, s3, s4, s5, s6, s7, s8, s9, s10, s11, s12, s13, \
+     d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, \
+     insert_time, insert_time1, insert_time2, insert_time3, i";
+
+/// 1950-01-01 .. 2150-01-01, so that the rows fall on both sides of the bounds used by the
+/// workloads, e.g. `d < '1970-01-01 00:00:00'`
+const TIMESTAMP_RANGE: std::ops::Range<i64> = -631_152_000..5_680_281_600;
+
+/// Generates the rows inserted by the suites, the same seed and stream always produce the same rows.
+///
+/// Each concurrent worker of a suite draws from its own stream, so that the rows do not depend
+/// on how the workers are scheduled.
+pub struct DataGen {
+    rng: ChaCha8Rng,
+}
+
+impl DataGen {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(stream);
+        Self { rng }
+    }
+
+    /// `VALUES` of `n` rows of `base`: (a int8, b bigint, c varchar, d datetime),
+    /// `c` is unique as the streams are consumed by merging on it
+    pub fn base_rows(&mut self, n: usize) -> String {
+        self.rows(n, |g, row| {
+            let a: i8 = g.rng.gen();
+            let b: i64 = g.rng.gen();
+            let c = g.uuid();
+            let d = g.timestamp();
+            write!(row, "({a}, {b}, '{c}', '{d}')")
+        })
+    }
+
+    /// `VALUES` of `n` rows of the tables of the vacuum suites, the `test` table of `auto-vacuum`
+    /// and `t1` of `vacuum2`: (id decimal, a variant, b varchar, c timestamp, d timestamp,
+    /// e decimal, f varchar, g varchar, h varchar)
+    pub fn vacuum_rows(&mut self, n: usize) -> String {
+        self.rows(n, |g, row| {
+            let id: u64 = g.rng.gen();
+            let a: u32 = g.rng.gen();
+            let b = g.string(16);
+            let c = g.timestamp();
+            let d = g.timestamp();
+            let e: u32 = g.rng.gen();
+            let (f, gg, h) = (g.string(8), g.string(8), g.string(8));
+            write!(
+                row,
+                "({id}, parse_json('{{\"k\": {a}}}'), '{b}', '{c}', '{d}', {e}, '{f}', '{gg}', '{h}')"
+            )
+        })
+    }
+
+    /// `VALUES` of `n` rows of `test_order`, of the given `id1` and `id2`
+    pub fn order_rows(&mut self, n: usize, id1: u64, id2: u64) -> String {
+        self.rows(n, |g, row| {
+            let id: u32 = g.rng.gen();
+            write!(row, "({id}, {id1}, {id2}")?;
+            for _ in 3..8 {
+                write!(row, ", {}", g.rng.gen::<i32>())?;
+            }
+            for _ in 0..13 {
+                write!(row, ", '{}'", g.string(10))?;
+            }
+            for _ in 0..10 {
+                let int: u32 = g.rng.gen_range(0..1_000_000_000);
+                let frac: u32 = g.rng.gen_range(0..100_000_000);
+                write!(row, ", {int}.{frac:08}")?;
+            }
+            for _ in 0..4 {
+                write!(row, ", '{}'", g.timestamp())?;
+            }
+            write!(row, ", {})", g.rng.gen::<i32>())
+        })
+    }
+
+    fn rows(
+        &mut self,
+        n: usize,
+        mut row: impl FnMut(&mut Self, &mut String) -> std::fmt::Result,
+    ) -> String {
+        let mut values = String::new();
+        for i in 0..n {
+            if i > 0 {
+                values.push_str(", ");
+            }
+            row(self, &mut values).expect("writing to a string never fails");
+        }
+        values
+    }
+
+    fn string(&mut self, len: usize) -> String {
+        (&mut self.rng)
+            .sample_iter(Alphanumeric)
+            .take(len)
+            .map(char::from)
+            .collect()
+    }
+
+    fn uuid(&mut self) -> String {
+        let n: u128 = self.rng.gen();
+        let hex = format!("{n:032x}");
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    fn timestamp(&mut self) -> String {
+        let secs = self.rng.gen_range(TIMESTAMP_RANGE);
+        DateTime::from_timestamp(secs, 0)
+            .expect("timestamp in range")
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+/// A query of the rows of `values` as a table of `columns`, e.g. the source of a `merge into`
+pub fn values_source(values: &str, columns: &str) -> String {
+    format!("select * from (values {values}) as s({columns})")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_and_stream_same_rows() {
+        assert_eq!(
+            DataGen::new(42, 1).base_rows(10),
+            DataGen::new(42, 1).base_rows(10)
+        );
+        assert_ne!(
+            DataGen::new(42, 1).base_rows(10),
+            DataGen::new(42, 2).base_rows(10)
+        );
+        assert_ne!(
+            DataGen::new(42, 1).base_rows(10),
+            DataGen::new(43, 1).base_rows(10)
+        );
+    }
+
+    #[test]
+    fn rows_have_all_the_columns() {
+        let mut gen = DataGen::new(0, 0);
+        let row = gen.order_rows(1, 7, 49);
+        assert!(row.starts_with('(') && row.ends_with(')'));
+        assert!(row.contains(", 7, 49, "));
+        // no quote, comma or parenthesis in the generated strings, splitting on ", " counts the columns
+        assert_eq!(row.split(", ").count(), ORDER_COLUMNS.split(", ").count());
+        assert_eq!(
+            gen.base_rows(1).split(", ").count(),
+            BASE_COLUMNS.split(", ").count()
+        );
+        assert_eq!(gen.vacuum_rows(3).matches("parse_json").count(), 3);
+    }
+}
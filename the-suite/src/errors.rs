@@ -0,0 +1,207 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+/// Category of the error of a failed operation, which tells the expected failures of a concurrent
+/// workload, e.g. a lost race between two mutations of a table, from the bugs it is looking for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Lost a race against a concurrent mutation or transaction
+    Conflict,
+    /// A transient failure of the server, the operation may succeed if issued again
+    Retryable,
+    Timeout,
+    /// The server could not be reached, or the connection was lost
+    Connection,
+    /// Data of a table is missing or unreadable
+    Corruption,
+    /// Anything else, e.g. a panic of the server
+    Unknown,
+}
+
+impl ErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Conflict => "conflict",
+            ErrorCategory::Retryable => "retryable",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::Corruption => "corruption",
+            ErrorCategory::Unknown => "unknown",
+        }
+    }
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Categories of the error codes of Databend, see `ErrorCode` of `databend-common-exception`
+const CODES: &[(u16, ErrorCategory)] = &[
+    // TableVersionMismatched
+    (2009, ErrorCategory::Conflict),
+    // StreamVersionMismatched
+    (2735, ErrorCategory::Conflict),
+    // UnresolvableConflict
+    (4001, ErrorCategory::Conflict),
+    // TransactionTimeout
+    (4003, ErrorCategory::Timeout),
+    // StorageNotFound
+    (3001, ErrorCategory::Corruption),
+    // StorageUnavailable
+    (3901, ErrorCategory::Retryable),
+];
+
+/// Categories of the errors whose code tells nothing, by their lowercase messages, the first match
+/// wins. Corruption comes before the conflicts, e.g. a "checksum mismatch" of a block is no lost race.
+/// A `CurrentTransactionIsAborted` (4002) is not mapped: it only follows an earlier failed statement
+/// of the transaction, whose error is the one that tells what happened
+const MESSAGES: &[(&str, ErrorCategory)] = &[
+    ("panic", ErrorCategory::Unknown),
+    ("corrupt", ErrorCategory::Corruption),
+    ("checksum", ErrorCategory::Corruption),
+    ("file not found", ErrorCategory::Corruption),
+    ("conflict", ErrorCategory::Conflict),
+    ("mismatch", ErrorCategory::Conflict),
+    ("timeout", ErrorCategory::Timeout),
+    ("timed out", ErrorCategory::Timeout),
+    ("please retry", ErrorCategory::Retryable),
+    ("try again", ErrorCategory::Retryable),
+];
+
+/// The errors the operations of the suites fail with
+pub trait OpError: Display {
+    fn category(&self) -> ErrorCategory;
+}
+
+impl OpError for databend_driver::Error {
+    fn category(&self) -> ErrorCategory {
+        use databend_driver::Error;
+        match self {
+            Error::Api(e) => client_error_category(e),
+            Error::Transport(_) | Error::IO(_) => ErrorCategory::Connection,
+            _ => ErrorCategory::Unknown,
+        }
+    }
+}
+
+/// The driver error the context was added to, if any
+impl OpError for anyhow::Error {
+    fn category(&self) -> ErrorCategory {
+        self.chain()
+            .find_map(|e| e.downcast_ref::<databend_driver::Error>())
+            .map_or(ErrorCategory::Unknown, OpError::category)
+    }
+}
+
+fn client_error_category(e: &databend_client::Error) -> ErrorCategory {
+    use databend_client::Error;
+    match e {
+        Error::WithContext(e, _) => client_error_category(e),
+        Error::QueryFailed(ec) | Error::Logic(_, ec) | Error::AuthFailure(ec) => {
+            match CODES.iter().find(|(code, _)| *code == ec.code) {
+                Some((_, category)) => *category,
+                None => message_category(&ec.message),
+            }
+        }
+        // the query is gone from the server, e.g. restarted or routed to another node
+        Error::Request(_) | Error::IO(_) | Error::QueryNotFound(_) => ErrorCategory::Connection,
+        Error::Response { status, .. } if status.is_server_error() => ErrorCategory::Retryable,
+        _ => ErrorCategory::Unknown,
+    }
+}
+
+fn message_category(message: &str) -> ErrorCategory {
+    let message = message.to_lowercase();
+    MESSAGES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))
+        .map_or(ErrorCategory::Unknown, |(_, category)| *category)
+}
+
+/// The Databend error code of a failed query
+pub fn error_code(e: &databend_driver::Error) -> Option<u16> {
+    fn client_error_code(e: &databend_client::Error) -> Option<u16> {
+        use databend_client::Error;
+        match e {
+            Error::QueryFailed(ec) | Error::Logic(_, ec) | Error::AuthFailure(ec) => Some(ec.code),
+            Error::WithContext(e, _) => client_error_code(e),
+            _ => None,
+        }
+    }
+    match e {
+        databend_driver::Error::Api(e) => client_error_code(e),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    fn query_failed(code: u16, message: &str) -> databend_driver::Error {
+        let error_code = serde_json::from_value(serde_json::json!({
+            "code": code,
+            "message": message,
+        }))
+        .unwrap();
+        databend_driver::Error::Api(databend_client::Error::QueryFailed(error_code))
+    }
+
+    #[test]
+    fn categories_of_the_codes_and_messages() {
+        let category = |code, message| query_failed(code, message).category();
+        assert_eq!(
+            category(2009, "table version mismatched"),
+            ErrorCategory::Conflict
+        );
+        assert_eq!(
+            category(3001, "segment not found"),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            category(1001, "file not found: 1/2/_b/abc.parquet"),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(category(1001, "query timed out"), ErrorCategory::Timeout);
+        assert_eq!(
+            category(1001, "block checksum mismatch"),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            category(1001, "checksum mismatch"),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            category(4002, "transaction aborted"),
+            ErrorCategory::Unknown
+        );
+        assert_eq!(
+            category(1104, "panicked at a conflict"),
+            ErrorCategory::Unknown
+        );
+        assert_eq!(category(1065, "syntax error"), ErrorCategory::Unknown);
+        assert_eq!(
+            databend_driver::Error::Transport("reset".to_owned()).category(),
+            ErrorCategory::Connection
+        );
+    }
+
+    #[test]
+    fn category_behind_a_context() {
+        let e = Err::<(), _>(query_failed(4001, "conflict"))
+            .context("failed to execute statement")
+            .unwrap_err();
+        assert_eq!(e.category(), ErrorCategory::Conflict);
+        assert_eq!(
+            anyhow::anyhow!("row count mismatch").category(),
+            ErrorCategory::Unknown
+        );
+        assert_eq!(error_code(&query_failed(4001, "conflict")), Some(4001));
+    }
+}
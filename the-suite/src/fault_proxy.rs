@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use log::{debug, info, warn};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+
+/// Network faults between the suites and the server, see [`FaultProxy`]
+#[derive(Args, Clone, Debug)]
+pub struct FaultArgs {
+    /// Route the connections through a local TCP proxy injecting these faults into the responses
+    /// of the server while the suites execute their workloads, e.g.
+    /// `drop=0.01,reset=0.01,stall=0.005,stall-for=10s,latency=20ms`, see the README
+    #[arg(long, global = true, value_parser = parse_faults)]
+    pub faults: Option<Faults>,
+
+    /// Inject one of the faults every period, in turn, instead of at random
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    pub fault_every: Option<Duration>,
+}
+
+/// The faults of [`FaultArgs::faults`], the probabilities are per chunk of a response
+#[derive(Clone, Debug, PartialEq)]
+pub struct Faults {
+    /// Closes the connection in the middle of a response
+    pub drop: f64,
+    /// Resets the connection in the middle of a response
+    pub reset: f64,
+    /// Stops forwarding a response for `stall_for`
+    pub stall: f64,
+    pub stall_for: Duration,
+    /// Added to every chunk of the requests and of the responses
+    pub latency: Duration,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            drop: 0.0,
+            reset: 0.0,
+            stall: 0.0,
+            stall_for: Duration::from_secs(5),
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+fn parse_faults(s: &str) -> Result<Faults> {
+    let mut faults = Faults::default();
+    for fault in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let (name, value) = fault
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid fault `{fault}`, expected `name=value`"))?;
+        let probability = || -> Result<f64> {
+            match value.parse() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(anyhow!(
+                    "invalid probability `{value}` of `{name}`, expected 0 to 1"
+                )),
+            }
+        };
+        match name {
+            "drop" => faults.drop = probability()?,
+            "reset" => faults.reset = probability()?,
+            "stall" => faults.stall = probability()?,
+            "stall-for" => faults.stall_for = humantime::parse_duration(value)?,
+            "latency" => faults.latency = humantime::parse_duration(value)?,
+            name => {
+                return Err(anyhow!(
+                    "unknown fault `{name}`, expected `drop`, `reset`, `stall`, `stall-for` or `latency`"
+                ))
+            }
+        }
+    }
+    Ok(faults)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fault {
+    Drop,
+    Reset,
+    Stall,
+}
+
+impl Fault {
+    fn name(self) -> &'static str {
+        match self {
+            Fault::Drop => "drop",
+            Fault::Reset => "reset",
+            Fault::Stall => "stall",
+        }
+    }
+}
+
+/// A TCP proxy on localhost in front of the server, injecting faults into its connections while
+/// active, see [`FaultProxy::inject`]. The random faults of a connection are drawn from a generator
+/// seeded by the seed of the run and the number of the connection.
+pub struct FaultProxy {
+    addr: SocketAddr,
+    state: Arc<State>,
+}
+
+struct State {
+    upstream: SocketAddr,
+    faults: Faults,
+    /// Faults on a schedule: the period, and when the next one is due
+    every: Option<(Duration, Mutex<Instant>)>,
+    seed: u64,
+    active: AtomicBool,
+    connections: AtomicU64,
+    /// Number of the faults injected so far by kind, including the latencies
+    injected: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl FaultProxy {
+    /// Listens on a free port of localhost, forwarding to `upstream`, a `host:port`
+    pub async fn start(upstream: &str, args: &FaultArgs, seed: u64) -> Result<Self> {
+        let upstream = tokio::net::lookup_host(upstream)
+            .await
+            .with_context(|| format!("failed to resolve {upstream}"))?
+            .next()
+            .ok_or_else(|| anyhow!("{upstream} resolves to no address"))?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State {
+            upstream,
+            faults: args.faults.clone().unwrap_or_default(),
+            every: args
+                .fault_every
+                .map(|every| (every, Mutex::new(Instant::now() + every))),
+            seed,
+            active: AtomicBool::new(false),
+            connections: AtomicU64::new(0),
+            injected: Mutex::default(),
+        });
+        let accepting = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let client = match listener.accept().await {
+                    Ok((client, _)) => client,
+                    Err(e) => {
+                        warn!("fault proxy failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+                let state = accepting.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = state.forward(client).await {
+                        debug!("fault proxy connection closed: {e}");
+                    }
+                });
+            }
+        });
+        info!(
+            "fault proxy on {addr} forwarding to {upstream}: {:?}",
+            state.faults
+        );
+        Ok(Self { addr, state })
+    }
+
+    /// `dsn` of the server behind the proxy, routed through it
+    pub fn route(&self, dsn: &str) -> Result<String> {
+        let (prefix, _, path) = split_dsn(dsn)?;
+        Ok(format!("{prefix}{}{path}", self.addr))
+    }
+
+    /// Starts injecting the faults, until the returned guard is dropped
+    pub fn inject(&self) -> InjectGuard<'_> {
+        self.state.active.store(true, Ordering::Relaxed);
+        InjectGuard { proxy: self }
+    }
+
+    /// Number of the faults injected so far by kind
+    pub fn injected(&self) -> BTreeMap<&'static str, u64> {
+        self.state.injected.lock().unwrap().clone()
+    }
+}
+
+/// Faults are injected while it lives, see [`FaultProxy::inject`]
+pub struct InjectGuard<'a> {
+    proxy: &'a FaultProxy,
+}
+
+impl Drop for InjectGuard<'_> {
+    fn drop(&mut self) {
+        self.proxy.state.active.store(false, Ordering::Relaxed);
+    }
+}
+
+impl State {
+    async fn forward(self: Arc<Self>, client: TcpStream) -> Result<()> {
+        let connection = self.connections.fetch_add(1, Ordering::Relaxed);
+        let rng = ChaCha8Rng::seed_from_u64(self.seed ^ connection.rotate_left(32));
+        let server = TcpStream::connect(self.upstream).await?;
+        let (client_read, client_write) = client.into_split();
+        let (server_read, server_write) = server.into_split();
+        let requests = self.requests(client_read, server_write);
+        let responses = self.responses(server_read, client_write, rng);
+        tokio::pin!(responses);
+        tokio::select! {
+            result = &mut responses => result,
+            // the responses to the last requests are still to be forwarded
+            result = requests => {
+                result?;
+                responses.await
+            }
+        }
+    }
+
+    async fn requests(&self, mut from: OwnedReadHalf, mut to: OwnedWriteHalf) -> Result<()> {
+        let mut buf = vec![0; 16 * 1024];
+        loop {
+            let n = from.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(to.shutdown().await?);
+            }
+            self.delay().await;
+            to.write_all(&buf[..n]).await?;
+        }
+    }
+
+    async fn responses(
+        &self,
+        mut from: OwnedReadHalf,
+        mut to: OwnedWriteHalf,
+        mut rng: ChaCha8Rng,
+    ) -> Result<()> {
+        let mut buf = vec![0; 16 * 1024];
+        loop {
+            let n = from.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(to.shutdown().await?);
+            }
+            self.delay().await;
+            match self.next_fault(&mut rng) {
+                None => {}
+                Some(Fault::Stall) => tokio::time::sleep(self.faults.stall_for).await,
+                Some(Fault::Drop) => {
+                    // half of the response goes through
+                    to.write_all(&buf[..n / 2]).await?;
+                    to.shutdown().await?;
+                    return Ok(());
+                }
+                Some(Fault::Reset) => {
+                    to.write_all(&buf[..n / 2]).await?;
+                    // closing with a zero linger sends a RST instead of a FIN
+                    to.as_ref().set_linger(Some(Duration::ZERO))?;
+                    to.forget();
+                    return Ok(());
+                }
+            }
+            to.write_all(&buf[..n]).await?;
+        }
+    }
+
+    async fn delay(&self) {
+        if self.active.load(Ordering::Relaxed) && !self.faults.latency.is_zero() {
+            self.count("latency");
+            tokio::time::sleep(self.faults.latency).await;
+        }
+    }
+
+    fn next_fault(&self, rng: &mut ChaCha8Rng) -> Option<Fault> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        let faults = [
+            (Fault::Drop, self.faults.drop),
+            (Fault::Reset, self.faults.reset),
+            (Fault::Stall, self.faults.stall),
+        ];
+        let fault = match &self.every {
+            None => faults
+                .into_iter()
+                .find(|(_, probability)| rng.gen_bool(*probability))
+                .map(|(fault, _)| fault),
+            Some((every, next)) => {
+                let mut next = next.lock().unwrap();
+                if Instant::now() < *next {
+                    return None;
+                }
+                *next = Instant::now() + *every;
+                // in turn, among those of a non-zero probability
+                let enabled: Vec<Fault> = faults
+                    .into_iter()
+                    .filter(|(_, probability)| *probability > 0.0)
+                    .map(|(fault, _)| fault)
+                    .collect();
+                let injected: u64 = enabled.iter().map(|f| self.count_of(f.name())).sum();
+                enabled
+                    .get(injected as usize % enabled.len().max(1))
+                    .copied()
+            }
+        };
+        if let Some(fault) = fault {
+            debug!("fault proxy: {}", fault.name());
+            self.count(fault.name());
+        }
+        fault
+    }
+
+    fn count(&self, kind: &'static str) {
+        *self.injected.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    fn count_of(&self, kind: &str) -> u64 {
+        self.injected
+            .lock()
+            .unwrap()
+            .get(kind)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// The `host:port` of the server of `dsn`, of the default port 8000 if unspecified
+pub fn upstream(dsn: &str) -> Result<String> {
+    let (_, host, _) = split_dsn(dsn)?;
+    if host.contains(':') {
+        Ok(host.to_owned())
+    } else {
+        Ok(format!("{host}:8000"))
+    }
+}
+
+/// (`scheme://user:password@`, `host[:port]`, `/database?options`)
+pub fn split_dsn(dsn: &str) -> Result<(&str, &str, &str)> {
+    let scheme_end = dsn
+        .find("://")
+        .ok_or_else(|| anyhow!("invalid DSN `{dsn}`"))?
+        + 3;
+    let rest = &dsn[scheme_end..];
+    let authority_end = scheme_end + rest.find(['/', '?']).unwrap_or(rest.len());
+    let host_start = dsn[..authority_end]
+        .rfind('@')
+        .map_or(scheme_end, |at| at + 1);
+    if host_start == authority_end {
+        return Err(anyhow!("DSN `{dsn}` has no host"));
+    }
+    Ok((
+        &dsn[..host_start],
+        &dsn[host_start..authority_end],
+        &dsn[authority_end..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorCategory;
+    use crate::mock::{MockServer, Reply};
+    use crate::suite::{SuiteContext, SuiteEntry};
+    use crate::vacuum2::Vacuum2Suite;
+
+    #[test]
+    fn faults_and_dsn() {
+        let faults = parse_faults("drop=0.1, stall=0.5,stall-for=2s,latency=20ms").unwrap();
+        assert_eq!(
+            faults,
+            Faults {
+                drop: 0.1,
+                stall: 0.5,
+                stall_for: Duration::from_secs(2),
+                latency: Duration::from_millis(20),
+                ..Default::default()
+            }
+        );
+        assert!(parse_faults("drop=2").is_err());
+        assert!(parse_faults("flood=0.1").is_err());
+
+        let dsn = "databend://root:p@ss@db.local:8001/default?sslmode=disable";
+        assert_eq!(upstream(dsn).unwrap(), "db.local:8001");
+        assert_eq!(upstream("databend://localhost").unwrap(), "localhost:8000");
+        assert!(upstream("databend://root@/default").is_err());
+    }
+
+    /// Echoes every request back, in one response each
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 1024];
+                    while let Ok(n @ 1..) = conn.read(&mut buf).await {
+                        if conn.write_all(&buf[..n]).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn round_trip(proxy: &FaultProxy) -> std::io::Result<Vec<u8>> {
+        let mut conn = TcpStream::connect(proxy.addr).await?;
+        conn.write_all(b"select 1").await?;
+        conn.shutdown().await?;
+        let mut response = vec![];
+        conn.read_to_end(&mut response).await?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn injects_the_faults_while_active() {
+        let upstream = echo_server().await.to_string();
+        let args = FaultArgs {
+            faults: Some(parse_faults("reset=1").unwrap()),
+            fault_every: None,
+        };
+        let proxy = FaultProxy::start(&upstream, &args, 42).await.unwrap();
+        let dsn = proxy
+            .route("databend://root:@localhost:8000/default")
+            .unwrap();
+        assert_eq!(dsn, format!("databend://root:@{}/default", proxy.addr));
+        assert_eq!(round_trip(&proxy).await.unwrap(), b"select 1");
+
+        {
+            let _faults = proxy.inject();
+            let response = round_trip(&proxy).await;
+            // the half of the response before the reset may be read or lost
+            assert!(response.map_or(true, |r| r.len() < b"select 1".len()));
+        }
+        assert_eq!(round_trip(&proxy).await.unwrap(), b"select 1");
+        assert_eq!(proxy.injected(), BTreeMap::from([("reset", 1)]));
+    }
+
+    #[tokio::test]
+    async fn faults_on_a_schedule() {
+        let upstream = echo_server().await.to_string();
+        let args = FaultArgs {
+            faults: Some(parse_faults("drop=0.1,stall=0.1,stall-for=1ms").unwrap()),
+            fault_every: Some(Duration::ZERO),
+        };
+        let proxy = FaultProxy::start(&upstream, &args, 42).await.unwrap();
+        let _faults = proxy.inject();
+        // a drop then a stall, in turn
+        assert_eq!(round_trip(&proxy).await.unwrap(), b"sele");
+        assert_eq!(round_trip(&proxy).await.unwrap(), b"select 1");
+        assert_eq!(
+            proxy.injected(),
+            BTreeMap::from([("drop", 1), ("stall", 1)])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn suites_tolerate_the_lost_connections_of_the_faults() {
+        let entry = SuiteEntry::of::<Vacuum2Suite>();
+        let matches = entry
+            .command()
+            .try_get_matches_from(["vacuum2", "--writers", "2", "--inserts-per-thread", "3"])
+            .unwrap();
+        let run = |ctx: SuiteContext| entry.start(&matches, ctx).unwrap();
+        let mock = || {
+            let mock = MockServer::new();
+            mock.when("INSERT INTO t1", Reply::transport("reset").times(1));
+            mock.when("INSERT INTO t1", Reply::error(4003, "timed out").times(1));
+            mock
+        };
+
+        let ctx = mock().context();
+        let error = run(ctx).await.unwrap_err();
+        assert!(error.to_string().contains("errors not allowed"), "{error}");
+
+        let upstream = echo_server().await.to_string();
+        let args = FaultArgs {
+            faults: Some(parse_faults("reset=0.1").unwrap()),
+            fault_every: None,
+        };
+        let proxy = FaultProxy::start(&upstream, &args, 42).await.unwrap();
+        let ctx = mock().context().with_fault_proxy(Arc::new(proxy));
+        run(ctx.clone()).await.unwrap();
+        let errors = ctx.stats().errors();
+        assert_eq!(errors[&ErrorCategory::Connection], 1);
+        assert_eq!(errors[&ErrorCategory::Timeout], 1);
+    }
+}
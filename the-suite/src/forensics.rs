@@ -0,0 +1,225 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use databend_driver::Connection;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::StreamExt;
+use log::{info, warn};
+
+use crate::namespace;
+use crate::suite::SuiteContext;
+use crate::util::ConnectionExt;
+
+/// Rows of `system.query_log` in a bundle at most
+const QUERY_LOG_LIMIT: usize = 10000;
+
+/// Collects the state of the server left behind by the failed run of `suite` into the
+/// directory `{dir}/{suite}_{run_id}`, and bundles it into a tarball next to it to attach to an issue:
+///
+/// - `failure.txt`, the error the run failed with
+/// - `tables.tsv`, the tables and streams of the databases of the run
+/// - `{database}.{table}/`, `SHOW CREATE TABLE` and the `fuse_snapshot`, `fuse_segment` and
+///   `fuse_block` of every table
+/// - `streams.tsv`, `metrics.tsv` and `settings.tsv`, from the `system` tables
+/// - `query_log.tsv`, the queries of the run, those on its databases since it started
+///
+/// The queries failing, e.g. on a server down, are noted in their files instead of failing the
+/// collection. Returns the path of the tarball.
+pub async fn collect(
+    ctx: &SuiteContext,
+    dir: &Path,
+    suite: &str,
+    failure: &anyhow::Error,
+) -> Result<PathBuf> {
+    let name = format!("{suite}_{}", ctx.run_id());
+    let bundle = dir.join(&name);
+    std::fs::create_dir_all(&bundle)
+        .with_context(|| format!("failed to create {}", bundle.display()))?;
+    write(&bundle.join("failure.txt"), format!("{failure:?}\n"))?;
+
+    let conn = ctx.new_connection().await?;
+    let databases = ctx
+        .databases()
+        .iter()
+        .map(|db| format!("'{db}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if !databases.is_empty() {
+        let tables = format!(
+            "SELECT database, name, engine FROM system.tables WHERE database IN ({databases}) \
+             ORDER BY database, name"
+        );
+        dump(&*conn, &tables, &bundle.join("tables.tsv")).await?;
+        for row in conn.query_strings(&tables).await.unwrap_or_default() {
+            let [database, table, engine] = row.as_slice() else {
+                continue;
+            };
+            if engine.eq_ignore_ascii_case("stream") {
+                continue;
+            }
+            let dir = bundle.join(format!("{database}.{table}"));
+            std::fs::create_dir_all(&dir)?;
+            let show = format!("SHOW CREATE TABLE {database}.{table}");
+            dump(&*conn, &show, &dir.join("show_create_table.tsv")).await?;
+            for function in ["fuse_snapshot", "fuse_segment", "fuse_block"] {
+                let sql = format!("SELECT * FROM {function}('{database}', '{table}')");
+                dump(&*conn, &sql, &dir.join(format!("{function}.tsv"))).await?;
+            }
+        }
+        let streams = format!("SELECT * FROM system.streams WHERE database IN ({databases})");
+        dump(&*conn, &streams, &bundle.join("streams.tsv")).await?;
+    }
+
+    dump(
+        &*conn,
+        "SELECT * FROM system.metrics",
+        &bundle.join("metrics.tsv"),
+    )
+    .await?;
+    dump(
+        &*conn,
+        "SELECT * FROM system.settings",
+        &bundle.join("settings.tsv"),
+    )
+    .await?;
+    // the names of the databases of the run and the query ids its statements are tagged with
+    // contain its id, the other queries of the run run in one of its databases
+    let since = namespace::run_started_at(&namespace::database_name(ctx.run_id(), ""))
+        .map(|started| {
+            format!(
+                "event_time >= '{}' AND ",
+                started.format("%Y-%m-%d %H:%M:%S")
+            )
+        })
+        .unwrap_or_default();
+    let query_log = format!(
+        "SELECT * FROM system.query_log WHERE {since}(current_database LIKE '{prefix}%' \
+         OR query_text LIKE '%{prefix}%') ORDER BY event_time LIMIT {QUERY_LOG_LIMIT}",
+        prefix = namespace::database_name(ctx.run_id(), ""),
+    );
+    dump(&*conn, &query_log, &bundle.join("query_log.tsv")).await?;
+
+    let tarball = dir.join(format!("{name}.tar.gz"));
+    let file = File::create(&tarball)
+        .with_context(|| format!("failed to create {}", tarball.display()))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    tar.append_dir_all(&name, &bundle)?;
+    tar.into_inner()?.finish()?;
+    Ok(tarball)
+}
+
+/// Collects the bundle of a failed run, see [`collect`], a failure of the collection is only logged
+pub async fn collect_on_failure(
+    ctx: &SuiteContext,
+    dir: &Path,
+    suite: &str,
+    failure: &anyhow::Error,
+) {
+    info!(
+        "===== [{suite}] collecting forensics into {} =====",
+        dir.display()
+    );
+    match collect(ctx, dir, suite, failure).await {
+        Ok(tarball) => info!(
+            "===== [{suite}] forensics bundle: {} =====",
+            tarball.display()
+        ),
+        Err(e) => warn!("===== [{suite}] failed to collect forensics: {e:#} ====="),
+    }
+}
+
+/// Writes the result of `sql` as tab separated values with a header, or its error
+async fn dump(conn: &dyn Connection, sql: &str, path: &Path) -> Result<()> {
+    let mut out = format!("-- {sql}\n");
+    match conn.query_iter(sql).await {
+        Ok(mut rows) => {
+            let schema = rows.schema();
+            let header: Vec<&str> = schema.fields().iter().map(|f| f.name.as_str()).collect();
+            if !header.is_empty() {
+                writeln!(out, "{}", header.join("\t"))?;
+            }
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(row) => {
+                        let values: Vec<String> =
+                            row.values().iter().map(|v| v.to_string()).collect();
+                        writeln!(out, "{}", values.join("\t"))?;
+                    }
+                    Err(e) => {
+                        writeln!(out, "-- failed: {e}")?;
+                        break;
+                    }
+                }
+            }
+        }
+        Err(e) => writeln!(out, "-- failed: {e}")?,
+    }
+    write(path, out)
+}
+
+fn write(path: &Path, content: String) -> Result<()> {
+    std::fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::mock::{string, uint, MockServer, Reply};
+
+    #[tokio::test]
+    async fn bundles_the_tables_of_the_run() {
+        let mock = MockServer::new();
+        let ctx = mock.context();
+        let database = ctx.database("test_stream");
+        mock.when(
+            "FROM system.tables",
+            Reply::rows(vec![
+                vec![string(&database), string("base"), string("FUSE")],
+                vec![string(&database), string("s"), string("STREAM")],
+            ]),
+        );
+        mock.when(
+            "fuse_snapshot",
+            Reply::rows(vec![vec![string("snapshot_id"), uint(3)]]),
+        );
+        mock.when("fuse_block", Reply::error(1025, "unknown table"));
+
+        let dir = std::env::temp_dir().join(format!("forensics-{}", std::process::id()));
+        let tarball = collect(&ctx, &dir, "change_tracking", &anyhow!("rows mismatch"))
+            .await
+            .unwrap();
+
+        let bundle = dir.join(format!("change_tracking_{}", ctx.run_id()));
+        let read = |file: &str| std::fs::read_to_string(bundle.join(file)).unwrap();
+        assert!(read("failure.txt").contains("rows mismatch"));
+        let table = format!("{database}.base");
+        assert!(read(&format!("{table}/fuse_snapshot.tsv")).ends_with("snapshot_id\t3\n"));
+        assert!(read(&format!("{table}/fuse_block.tsv")).contains("-- failed:"));
+        assert!(
+            !bundle.join(format!("{database}.s")).exists(),
+            "streams have no snapshots"
+        );
+        assert_eq!(mock.count(&format!("SHOW CREATE TABLE {table}")), 1);
+        assert!(read("query_log.tsv").contains(&format!("LIKE '%ts_{}_%'", ctx.run_id())));
+
+        let mut gz = flate2::read::GzDecoder::new(File::open(&tarball).unwrap());
+        let mut tar = Vec::new();
+        gz.read_to_end(&mut tar).unwrap();
+        let mut archive = tar::Archive::new(tar.as_slice());
+        let entries: Vec<PathBuf> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+        let metrics = format!("change_tracking_{}/metrics.tsv", ctx.run_id());
+        assert!(entries.contains(&PathBuf::from(metrics)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
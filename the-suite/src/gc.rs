@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use clap::Parser;
+use log::info;
+
+use crate::namespace::{self, LEGACY_DATABASES};
+use crate::suite::SuiteContext;
+use crate::util::ConnectionExt;
+
+/// Drop the databases left behind by the runs, e.g. by `--keep`, killed or crashed runs
+#[derive(Parser, Clone, Debug)]
+pub struct Args {
+    /// Drop the databases of the runs started longer ago than this, e.g. `12h`, `7days`.
+    /// It should be longer than the longest run still going on the server
+    #[arg(long, default_value = "24h", value_parser = humantime::parse_duration)]
+    older_than: Duration,
+
+    /// Only list the databases to drop
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// Also drop the fixed databases of the suites without tables, whose age is unknown
+    #[arg(long, default_value_t = false)]
+    force: bool,
+}
+
+/// Drops the databases of the runs older than `--older-than`, and the fixed databases of the suites
+/// from before the runs were namespaced if none of their tables is newer than that. Those without
+/// tables are only dropped with `--force`
+pub async fn run(args: Args, ctx: &SuiteContext) -> Result<()> {
+    let conn = ctx.new_connection().await?;
+    let databases: Vec<String> = conn
+        .query_strings("SELECT name FROM system.databases")
+        .await?
+        .into_iter()
+        .filter_map(|row| row.into_iter().next())
+        .collect();
+
+    let cutoff = Utc::now() - chrono::Duration::from_std(args.older_than)?;
+    let mut stale: Vec<String> = databases
+        .iter()
+        .filter(|db| namespace::run_started_at(db).is_some_and(|started| started < cutoff))
+        .cloned()
+        .collect();
+
+    // the legacy databases are as old as their newest table
+    let legacy: Vec<&String> = databases
+        .iter()
+        .filter(|db| LEGACY_DATABASES.contains(&db.as_str()))
+        .collect();
+    if !legacy.is_empty() {
+        let names = legacy
+            .iter()
+            .map(|db| format!("'{db}'"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "SELECT database, max(created_on) > DATE_SUB(SECOND, {}, now()) FROM system.tables \
+             WHERE database IN ({names}) GROUP BY database",
+            args.older_than.as_secs()
+        );
+        // whether the newest table of each legacy database with tables is recent
+        let recent: Vec<(String, bool)> = conn
+            .query_strings(&sql)
+            .await?
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [db, recent] => Some((db.clone(), recent == "true")),
+                _ => None,
+            })
+            .collect();
+        for db in legacy {
+            match recent.iter().find(|(name, _)| name == db) {
+                Some((_, true)) => {}
+                Some((_, false)) => stale.push(db.clone()),
+                None if args.force => stale.push(db.clone()),
+                None => {
+                    info!("skipping database {db} without tables, of unknown age, see --force")
+                }
+            }
+        }
+    }
+
+    if stale.is_empty() {
+        info!(
+            "no database older than {}",
+            humantime::format_duration(args.older_than)
+        );
+        return Ok(());
+    }
+    for database in &stale {
+        if args.dry_run {
+            info!("would drop database {database}");
+        } else {
+            info!("dropping database {database}");
+            conn.exec(&format!("DROP DATABASE IF EXISTS {database}"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, MockServer, Reply};
+
+    fn databases(mock: &MockServer, recent_run: &str) {
+        let names = [
+            "default",
+            "ts_20200101_120000_3fa2c1_test_stream",
+            recent_run,
+            "test_vacuum2",
+            "test_stream",
+            "test_txn",
+        ];
+        let rows = names.iter().map(|name| vec![string(name)]).collect();
+        mock.when("FROM system.databases", Reply::rows(rows));
+        let tables = vec![
+            vec![string("test_stream"), string("true")],
+            vec![string("test_vacuum2"), string("false")],
+        ];
+        mock.when("FROM system.tables", Reply::rows(tables));
+    }
+
+    #[tokio::test]
+    async fn drops_the_databases_older_than_the_age() {
+        let mock = MockServer::new();
+        let recent_run = namespace::database_name(&namespace::new_run_id(), "test_stream");
+        databases(&mock, &recent_run);
+        let args = Args::try_parse_from(["gc", "--older-than", "1h"]).unwrap();
+        run(args, &mock.context()).await.unwrap();
+
+        let drops: Vec<_> = mock
+            .statements()
+            .into_iter()
+            .filter(|sql| sql.starts_with("DROP DATABASE"))
+            .collect();
+        assert_eq!(
+            drops,
+            [
+                "DROP DATABASE IF EXISTS ts_20200101_120000_3fa2c1_test_stream",
+                "DROP DATABASE IF EXISTS test_vacuum2",
+            ]
+        );
+        assert_eq!(
+            mock.count("max(created_on) > DATE_SUB(SECOND, 3600, now())"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_the_databases_without_tables_with_force() {
+        let mock = MockServer::new();
+        databases(&mock, "ts_20200101_120000_3fa2c1_auto_vacuum");
+        let args = Args::try_parse_from(["gc", "--force"]).unwrap();
+        run(args, &mock.context()).await.unwrap();
+
+        assert_eq!(mock.count("DROP DATABASE IF EXISTS test_txn"), 1);
+        assert_eq!(mock.count("DROP DATABASE IF EXISTS test_stream"), 0);
+    }
+
+    #[tokio::test]
+    async fn dry_run_drops_nothing() {
+        let mock = MockServer::new();
+        databases(&mock, "ts_20200101_120000_3fa2c1_auto_vacuum");
+        let args = Args::try_parse_from(["gc", "--dry-run"]).unwrap();
+        run(args, &mock.context()).await.unwrap();
+
+        assert_eq!(mock.count("DROP DATABASE"), 0);
+    }
+}
@@ -0,0 +1,371 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use log::{info, warn};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::time::Instant;
+
+/// A local cluster started by the harness, instead of a server already running
+#[derive(Args, Clone, Debug)]
+pub struct LaunchArgs {
+    /// Start databend-meta and databend-query from the binaries of this directory, with their
+    /// data in a temporary directory, run the suites against them and stop them afterwards
+    #[arg(long, global = true, value_name = "BIN_DIR")]
+    pub launch: Option<PathBuf>,
+
+    /// Number of the databend-query nodes of the launched cluster
+    #[arg(long, global = true, default_value_t = 1)]
+    pub query_nodes: u32,
+
+    /// Directory of the configurations and logs of the launched servers, kept after the run
+    #[arg(long, global = true, default_value = "launch")]
+    pub launch_dir: PathBuf,
+}
+
+/// Ports of a databend-query node
+#[derive(Clone, Debug)]
+struct QueryPorts {
+    http: u16,
+    flight: u16,
+    admin: u16,
+    metric: u16,
+    mysql: u16,
+    clickhouse: u16,
+    flight_sql: u16,
+}
+
+/// A databend-query node of a [`LocalCluster`]
+struct QueryNode {
+    config: PathBuf,
+    log: PathBuf,
+    ports: QueryPorts,
+    process: Option<Child>,
+}
+
+/// databend-meta and databend-query nodes on localhost, started from binaries, storing their
+/// data on the local file system. They are killed and their data removed when the cluster is dropped.
+pub struct LocalCluster {
+    bin_dir: PathBuf,
+    /// Configurations and logs
+    dir: PathBuf,
+    /// Data of the meta and the tables, removed on drop
+    data_dir: PathBuf,
+    meta: Child,
+    queries: Vec<QueryNode>,
+}
+
+impl LocalCluster {
+    /// Starts the cluster and waits up to `wait` for every node to accept connections
+    pub async fn start(args: &LaunchArgs, wait: Duration) -> Result<Self> {
+        let bin_dir = args
+            .launch
+            .clone()
+            .ok_or_else(|| anyhow!("no `--launch` directory"))?;
+        for bin in ["databend-meta", "databend-query"] {
+            if !bin_dir.join(bin).is_file() {
+                return Err(anyhow!("no {bin} in {}", bin_dir.display()));
+            }
+        }
+        let dir = args
+            .launch_dir
+            .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
+        let data_dir = std::env::temp_dir().join(format!("databend-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        std::fs::create_dir_all(&data_dir)?;
+        info!(
+            "launching a cluster of {} query nodes from {}, logs in {}",
+            args.query_nodes,
+            bin_dir.display(),
+            dir.display()
+        );
+
+        let (admin, grpc, raft) = (free_port()?, free_port()?, free_port()?);
+        let config = dir.join("databend-meta.toml");
+        write(&config, &meta_config(&dir, &data_dir, admin, grpc, raft))?;
+        let out = dir.join("databend-meta.out");
+        let mut meta = spawn(&bin_dir.join("databend-meta"), &config, &out)?;
+        wait_for_port("databend-meta", &mut meta, &out, grpc, wait).await?;
+
+        let mut cluster = Self {
+            bin_dir,
+            dir,
+            data_dir,
+            meta,
+            queries: vec![],
+        };
+        for node in 1..=args.query_nodes {
+            let ports = QueryPorts {
+                http: free_port()?,
+                flight: free_port()?,
+                admin: free_port()?,
+                metric: free_port()?,
+                mysql: free_port()?,
+                clickhouse: free_port()?,
+                flight_sql: free_port()?,
+            };
+            let config = cluster.dir.join(format!("databend-query-{node}.toml"));
+            let logs = cluster.dir.join(format!("query-{node}"));
+            write(
+                &config,
+                &query_config(&logs, &cluster.data_dir, &ports, grpc),
+            )?;
+            cluster.queries.push(QueryNode {
+                config,
+                log: cluster.dir.join(format!("databend-query-{node}.out")),
+                ports,
+                process: None,
+            });
+        }
+        for node in 0..cluster.queries.len() {
+            cluster.start_query(node, wait).await?;
+        }
+        Ok(cluster)
+    }
+
+    /// DSN of the HTTP handler of each databend-query node
+    pub fn dsns(&self) -> Vec<String> {
+        self.queries
+            .iter()
+            .map(|q| {
+                format!(
+                    "databend://root:@127.0.0.1:{}/default?sslmode=disable",
+                    q.ports.http
+                )
+            })
+            .collect()
+    }
+
+    /// Number of the databend-query nodes
+    pub fn query_nodes(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Starts the databend-query `node`, e.g. again after [`LocalCluster::kill_query`], and waits
+    /// up to `wait` for it to accept connections
+    pub async fn start_query(&mut self, node: usize, wait: Duration) -> Result<()> {
+        let query = &mut self.queries[node];
+        let bin = self.bin_dir.join("databend-query");
+        let process = query
+            .process
+            .insert(spawn(&bin, &query.config, &query.log)?);
+        let name = format!("databend-query {}", node + 1);
+        wait_for_port(&name, process, &query.log, query.ports.http, wait).await
+    }
+
+    /// Kills the databend-query `node` with SIGKILL, as in a crash
+    pub async fn kill_query(&mut self, node: usize) -> Result<()> {
+        if let Some(mut process) = self.queries[node].process.take() {
+            process.kill().await?;
+        }
+        Ok(())
+    }
+
+    /// Starts the databend-query nodes killed, or exited, since, and waits up to `wait` for every
+    /// node to accept connections
+    pub async fn ensure_running(&mut self, wait: Duration) -> Result<()> {
+        for node in 0..self.queries.len() {
+            let query = &mut self.queries[node];
+            let running = match &mut query.process {
+                Some(process) => process.try_wait()?.is_none(),
+                None => false,
+            };
+            match &mut query.process {
+                Some(process) if running => {
+                    let name = format!("databend-query {}", node + 1);
+                    wait_for_port(&name, process, &query.log, query.ports.http, wait).await?;
+                }
+                _ => self.start_query(node, wait).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops all the nodes, their data is removed on drop and their logs are kept
+    pub async fn shutdown(&mut self) -> Result<()> {
+        for node in 0..self.queries.len() {
+            self.kill_query(node).await?;
+        }
+        self.meta.kill().await?;
+        info!("cluster stopped, logs in {}", self.dir.display());
+        Ok(())
+    }
+}
+
+impl Drop for LocalCluster {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.data_dir) {
+            warn!("failed to remove {}: {e}", self.data_dir.display());
+        }
+    }
+}
+
+fn free_port() -> Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Starts `bin` with the configuration file `config`, its output is appended to `out`
+fn spawn(bin: &Path, config: &Path, out: &Path) -> Result<Child> {
+    let stdout = File::options().create(true).append(true).open(out)?;
+    let stderr = stdout.try_clone()?;
+    Command::new(bin)
+        .arg("--config-file")
+        .arg(config)
+        .stdout(Stdio::from(stdout))
+        .stderr(Stdio::from(stderr))
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to start {}", bin.display()))
+}
+
+/// Waits for the `process` to listen on `port`, fails early if it exits, see its output `out`
+async fn wait_for_port(
+    name: &str,
+    process: &mut Child,
+    out: &Path,
+    port: u16,
+    wait: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + wait;
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        if let Some(status) = process.try_wait()? {
+            return Err(anyhow!(
+                "{name} exited with {status}, see {}",
+                out.display()
+            ));
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "{name} not listening on port {port} within {wait:?}"
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    info!("{name} listening on port {port}");
+    Ok(())
+}
+
+fn meta_config(dir: &Path, data_dir: &Path, admin: u16, grpc: u16, raft: u16) -> String {
+    format!(
+        r#"log_dir = "{logs}"
+admin_api_address = "127.0.0.1:{admin}"
+grpc_api_address = "127.0.0.1:{grpc}"
+grpc_api_advertise_host = "127.0.0.1"
+
+[raft_config]
+id = 1
+raft_dir = "{raft_dir}"
+raft_api_port = {raft}
+raft_listen_host = "127.0.0.1"
+raft_advertise_host = "127.0.0.1"
+single = true
+"#,
+        logs = dir.join("meta").display(),
+        raft_dir = data_dir.join("meta").display(),
+    )
+}
+
+fn query_config(logs: &Path, data_dir: &Path, ports: &QueryPorts, meta_grpc: u16) -> String {
+    format!(
+        r#"[query]
+flight_api_address = "127.0.0.1:{flight}"
+admin_api_address = "127.0.0.1:{admin}"
+metric_api_address = "127.0.0.1:{metric}"
+mysql_handler_host = "127.0.0.1"
+mysql_handler_port = {mysql}
+clickhouse_http_handler_host = "127.0.0.1"
+clickhouse_http_handler_port = {clickhouse}
+http_handler_host = "127.0.0.1"
+http_handler_port = {http}
+flight_sql_handler_host = "127.0.0.1"
+flight_sql_handler_port = {flight_sql}
+tenant_id = "test_tenant"
+cluster_id = "test_cluster"
+
+[[query.users]]
+name = "root"
+auth_type = "no_password"
+
+[log]
+[log.file]
+level = "INFO"
+format = "text"
+dir = "{logs}"
+
+[meta]
+endpoints = ["127.0.0.1:{meta_grpc}"]
+username = "root"
+password = "root"
+client_timeout_in_second = 60
+
+[storage]
+type = "fs"
+
+[storage.fs]
+data_path = "{data}"
+"#,
+        flight = ports.flight,
+        admin = ports.admin,
+        metric = ports.metric,
+        mysql = ports.mysql,
+        clickhouse = ports.clickhouse,
+        http = ports.http,
+        flight_sql = ports.flight_sql,
+        logs = logs.display(),
+        data = data_dir.join("data").display(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_are_valid_toml() {
+        let dir = Path::new("/tmp/launch");
+        let meta: toml::Table = toml::from_str(&meta_config(dir, dir, 1, 2, 3)).unwrap();
+        assert_eq!(meta["grpc_api_address"].as_str(), Some("127.0.0.1:2"));
+        assert_eq!(meta["raft_config"]["raft_api_port"].as_integer(), Some(3));
+
+        let ports = QueryPorts {
+            http: 8000,
+            flight: 9091,
+            admin: 8080,
+            metric: 7070,
+            mysql: 3307,
+            clickhouse: 8124,
+            flight_sql: 8900,
+        };
+        let query: toml::Table = toml::from_str(&query_config(dir, dir, &ports, 2)).unwrap();
+        assert_eq!(query["query"]["http_handler_port"].as_integer(), Some(8000));
+        assert_eq!(query["meta"]["endpoints"][0].as_str(), Some("127.0.0.1:2"));
+        assert_eq!(
+            query["storage"]["fs"]["data_path"].as_str(),
+            Some("/tmp/launch/data")
+        );
+    }
+
+    #[tokio::test]
+    async fn requires_the_binaries() {
+        let args = LaunchArgs {
+            launch: Some(std::env::temp_dir().join("no-such-bin-dir")),
+            query_nodes: 1,
+            launch_dir: std::env::temp_dir(),
+        };
+        let error = LocalCluster::start(&args, Duration::from_secs(1))
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("no databend-meta in"));
+    }
+}
@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use tokio::time::Instant;
+
+use crate::shutdown::Shutdown;
+
+/// How long the workers of a suite run and how fast they issue their operations
+#[derive(Args, Clone, Debug, Default)]
+pub struct LoadArgs {
+    /// Run the workers until this deadline instead of their numbers of iterations, e.g. `90s`,
+    /// `15m`
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+
+    /// Open loop: each worker issues its operations on a fixed schedule of this rate, e.g. `20/s`,
+    /// `300/m`, and their latencies are measured from their scheduled starts
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: Option<f64>,
+}
+
+impl LoadArgs {
+    /// The load of the workers started now, whose deadline they share. They stop early once
+    /// `shutdown` is requested
+    pub fn start(&self, shutdown: &Shutdown) -> Load {
+        Load {
+            deadline: self.duration.map(|d| Instant::now() + d),
+            interval: self.rate.map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            shutdown: shutdown.clone(),
+        }
+    }
+}
+
+/// Operations per second, of `N`, `N/s`, `N/m` or `N/h`
+fn parse_rate(s: &str) -> Result<f64> {
+    let (count, unit) = s.split_once('/').unwrap_or((s, "s"));
+    let count: f64 = count
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid rate `{s}`, e.g. `20/s`"))?;
+    let secs = match unit.trim() {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        unit => {
+            return Err(anyhow!(
+                "invalid unit `{unit}` of rate `{s}`, expected `s`, `m` or `h`"
+            ))
+        }
+    };
+    if count <= 0.0 || !count.is_finite() {
+        return Err(anyhow!("rate `{s}` must be positive"));
+    }
+    Ok(count / secs)
+}
+
+/// The load of a suite run, see [`LoadArgs`]
+#[derive(Clone, Debug, Default)]
+pub struct Load {
+    deadline: Option<Instant>,
+    interval: Option<Duration>,
+    shutdown: Shutdown,
+}
+
+impl Load {
+    /// Paces a worker of `iterations` operations, or of an unbounded number of them if `None`.
+    /// The iterations are ignored once a deadline is set.
+    pub fn pacer(&self, iterations: Option<u64>) -> Pacer {
+        Pacer {
+            load: self.clone(),
+            iterations,
+            started: Instant::now(),
+            next: Instant::now(),
+            done: 0,
+        }
+    }
+}
+
+/// Schedules the operations of a worker
+pub struct Pacer {
+    load: Load,
+    iterations: Option<u64>,
+    started: Instant,
+    /// Scheduled start of the next operation of an open loop
+    next: Instant,
+    done: u64,
+}
+
+impl Pacer {
+    /// Waits for the scheduled start of the next operation and returns it, `None` once the
+    /// worker is done or a shutdown is requested. A worker lagging behind its schedule is not
+    /// waited for, its operations start late and the lag counts in their latencies.
+    pub async fn next(&mut self) -> Option<std::time::Instant> {
+        if self.is_done() {
+            return None;
+        }
+        let scheduled = match self.load.interval {
+            None => Instant::now(),
+            Some(interval) => {
+                let scheduled = self.next;
+                self.next += interval;
+                if self
+                    .load
+                    .deadline
+                    .is_some_and(|deadline| scheduled >= deadline)
+                {
+                    return None;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep_until(scheduled) => {}
+                    _ = self.load.shutdown.requested() => return None,
+                }
+                scheduled
+            }
+        };
+        self.done += 1;
+        Some(scheduled.into_std())
+    }
+
+    fn is_done(&self) -> bool {
+        if self.load.shutdown.is_requested() {
+            return true;
+        }
+        match self.load.deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => self.iterations.is_some_and(|n| self.done >= n),
+        }
+    }
+
+    /// Number of the operations started so far
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    /// Percentage of the run done so far, of the time until the deadline or of the iterations
+    pub fn progress(&self) -> u64 {
+        match (self.load.deadline, self.iterations) {
+            (Some(deadline), _) => {
+                let total = deadline
+                    .saturating_duration_since(self.started)
+                    .as_secs_f64();
+                let elapsed = self.started.elapsed().as_secs_f64();
+                (elapsed * 100.0 / total.max(f64::EPSILON)).min(100.0) as u64
+            }
+            (None, Some(n)) => self.done * 100 / n.max(1),
+            (None, None) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse_rate("20").unwrap(), 20.0);
+        assert_eq!(parse_rate("20/s").unwrap(), 20.0);
+        assert_eq!(parse_rate("120/m").unwrap(), 2.0);
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("1/d").is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn open_loop_until_the_deadline() {
+        let shutdown = Shutdown::default();
+        let load = LoadArgs {
+            duration: Some(Duration::from_secs(1)),
+            rate: Some(10.0),
+        }
+        .start(&shutdown);
+        let mut pacer = load.pacer(Some(3));
+        let start = std::time::Instant::now();
+        let mut scheduled = vec![];
+        while let Some(at) = pacer.next().await {
+            scheduled.push(at.duration_since(start));
+        }
+        // the iterations are ignored, one operation every 100ms until the deadline
+        assert_eq!(scheduled.len(), 10);
+        assert_eq!(scheduled[9] - scheduled[8], Duration::from_millis(100));
+
+        let mut pacer = LoadArgs::default().start(&shutdown).pacer(Some(3));
+        let mut done = 0;
+        while pacer.next().await.is_some() {
+            done += 1;
+        }
+        assert_eq!((done, pacer.done(), pacer.progress()), (3, 3, 100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_on_shutdown() {
+        let shutdown = Shutdown::default();
+        let load = LoadArgs {
+            duration: None,
+            rate: Some(1.0),
+        }
+        .start(&shutdown);
+        let mut pacer = load.pacer(None);
+        assert!(pacer.next().await.is_some());
+
+        let requester = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            requester.request();
+        });
+        let start = Instant::now();
+        // the sleep until the next scheduled start is cut short
+        assert!(pacer.next().await.is_none());
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+        assert_eq!(pacer.done(), 1);
+    }
+}
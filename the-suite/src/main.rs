@@ -1,53 +1,308 @@
-use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::{anyhow, Result};
+
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
 use clap::Subcommand;
 use env_logger::Env;
-use log::info;
+use log::{info, warn};
 
 mod auto_vacuum;
 mod change_tracking;
+mod config;
+mod crash;
+mod datagen;
+mod errors;
 mod explict_txn;
+mod fault_proxy;
+mod forensics;
+mod gc;
+mod launcher;
+mod load;
+#[cfg(test)]
+mod mock;
 mod multi_table_insert;
+mod namespace;
+mod nodes;
+mod preflight;
+mod reconcile;
+mod record;
+mod replay;
+mod report;
+mod retry;
+mod row_diff;
+mod run_all;
+mod scenario;
+mod shutdown;
+mod stats;
+mod suite;
+mod task;
+mod upsert;
 mod util;
+mod vacuum;
 mod vacuum2;
 
-use auto_vacuum::Args as AutoVacuumArgs;
-use change_tracking::Args as ChangeTrackingArgs;
-use change_tracking::ChangeTrackingSuite;
-use vacuum2::Args as Vacuum2Args;
+use config::Config;
+use crash::Crasher;
+use fault_proxy::FaultProxy;
+use launcher::LocalCluster;
+use record::Recorder;
+use shutdown::Shutdown;
+use suite::SuiteContext;
 
+/// Test suites for Databend
+// the subcommands are built from the suites in `suite::registry()`
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[command(subcommand)]
-    command: Commands,
+    /// Configuration file of the server profiles, session settings and default arguments of the suites
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Profile of the configuration file to run against, instead of its `default_profile`
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// DSN of the server, overrides the profiles and `DATABEND_DSN`. Repeat it for each query node
+    /// of a cluster, to spread the connections over them
+    #[arg(long, global = true)]
+    dsn: Vec<String>,
+
+    /// Session setting of every new connection, overrides the configuration file
+    #[arg(long = "set", global = true, value_name = "NAME=VALUE", value_parser = config::parse_setting)]
+    settings: Vec<(String, String)>,
+
+    /// Write a JUnit XML report of the run, one test case per suite or matrix entry
+    #[arg(long, global = true)]
+    report_junit: Option<PathBuf>,
+
+    /// Write a JSON report of the run, one test case per suite or matrix entry
+    #[arg(long, global = true)]
+    report_json: Option<PathBuf>,
+
+    /// Seed of the generated data, random if not specified. Runs of the same seed insert the same rows
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Category of the errors the operations may fail with, instead of those allowed by the suite
+    #[arg(long = "allow-error", global = true, value_name = "CATEGORY")]
+    allowed_errors: Vec<errors::ErrorCategory>,
+
+    #[command(flatten)]
+    retry: retry::RetryArgs,
+
+    #[command(flatten)]
+    faults: fault_proxy::FaultArgs,
+
+    #[command(flatten)]
+    launch: launcher::LaunchArgs,
+
+    #[command(flatten)]
+    crash: crash::CrashArgs,
+
+    #[command(flatten)]
+    nodes: nodes::NodeArgs,
+
+    /// Record every statement issued by the run into this trace file, see the `replay` subcommand
+    #[arg(long, global = true)]
+    record: Option<PathBuf>,
+
+    /// Keep the databases of the run instead of dropping them once it is done
+    #[arg(long, global = true)]
+    keep: bool,
+
+    /// Skip the teardown of the failed suites, to inspect what they left behind
+    #[arg(long, global = true)]
+    keep_on_failure: bool,
+
+    /// Directory of the forensics bundles of the failed suites: their tables, snapshots, query log,
+    /// metrics and settings, as a tarball to attach to an issue
+    #[arg(long, global = true, default_value = "forensics")]
+    forensics_dir: PathBuf,
+
+    /// Do not collect the forensics bundles of the failed suites
+    #[arg(long, global = true)]
+    no_forensics: bool,
+
+    /// How long to wait for the server to accept connections before running the suites
+    #[arg(long, global = true, default_value = "60s", value_parser = humantime::parse_duration)]
+    wait_for_server: Duration,
+
+    /// Run the suites without checking what the server offers first, none of them is skipped
+    #[arg(long, global = true)]
+    no_preflight: bool,
+
+    /// On Ctrl-C or SIGTERM, how long the workers are given to stop before the partial state is verified
+    #[arg(long, global = true, default_value = "30s", value_parser = humantime::parse_duration)]
+    grace_period: Duration,
 }
 
+/// Subcommands of the harness itself, besides the suites
 #[derive(Subcommand, Debug)]
-enum Commands {
-    ChangeTracking(ChangeTrackingArgs),
-    ExplicitTxn,
-    MultiTableInsert,
-    AutoVacuum(AutoVacuumArgs),
-    Vacuum2(Vacuum2Args),
+enum HarnessCommands {
+    /// Run every entry of a test matrix file, see `matrix.toml`
+    RunAll(run_all::Args),
+    /// Re-issue a trace recorded by `--record` against the server
+    Replay(replay::Args),
+    /// Drop the databases left behind by the runs on the server
+    Gc(gc::Args),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let args = Args::parse();
-    let dsn = std::env::var("DATABEND_DSN").unwrap_or(
-        "databend://root:@localhost:8000/default?sslmode=disable"
-            .to_owned(),
-    );
-
-    info!("using DSN {}", dsn);
-    match args.command {
-        Commands::ChangeTracking(cmd_args) => ChangeTrackingSuite::run(cmd_args, dsn).await,
-        Commands::ExplicitTxn => explict_txn::run(dsn).await,
-        Commands::MultiTableInsert => multi_table_insert::run(dsn).await,
-        Commands::AutoVacuum(cmd_args) => auto_vacuum::run(cmd_args, dsn).await,
-        Commands::Vacuum2(cmd_args) => vacuum2::run(cmd_args, dsn).await,
+    // the defaults of the suites are needed to build their subcommands
+    let config = match config::config_path(std::env::args()) {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    let registry = config.apply_defaults(suite::registry())?;
+    let cmd = registry.iter().fold(Args::command(), |cmd, entry| {
+        cmd.subcommand(entry.command())
+    });
+    let matches = HarnessCommands::augment_subcommands(cmd)
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+
+    // a launched cluster wins over an explicit profile, which wins over `DATABEND_DSN`,
+    // which wins over the default profile
+    let cluster = match &args.launch.launch {
+        Some(_) => {
+            let cluster = LocalCluster::start(&args.launch, args.wait_for_server).await?;
+            Some(Arc::new(tokio::sync::Mutex::new(cluster)))
+        }
+        None => None,
+    };
+    let mut dsns = match &cluster {
+        Some(cluster) => cluster.lock().await.dsns(),
+        None => args.dsn.clone(),
+    };
+    let profile = config.profile(args.profile.as_deref())?;
+    if dsns.is_empty() {
+        let dsn = args
+            .profile
+            .as_ref()
+            .and(profile)
+            .map(|p| p.dsn.clone())
+            .or_else(|| std::env::var("DATABEND_DSN").ok())
+            .or_else(|| profile.map(|p| p.dsn.clone()))
+            .unwrap_or("databend://root:@localhost:8000/default?sslmode=disable".to_owned());
+        dsns.push(dsn);
+    }
+    if args.nodes.discover_nodes {
+        let conn = databend_driver::Client::new(dsns[0].clone())
+            .get_conn()
+            .await?;
+        dsns = nodes::discover(&*conn, &dsns[0]).await?;
+    }
+
+    info!("using DSN {}", dsns.join(", "));
+    let seed = args.seed.unwrap_or_else(rand::random);
+    info!("using seed {seed}, pass `--seed {seed}` to generate the same data");
+    // the faults are drawn from the seed of the data too, a proxy in front of each node
+    let mut fault_proxies = vec![];
+    if args.faults.faults.is_some() || args.faults.fault_every.is_some() {
+        for (node, dsn) in dsns.iter_mut().enumerate() {
+            let upstream = fault_proxy::upstream(dsn)?;
+            let seed = seed.wrapping_add(node as u64);
+            let proxy = FaultProxy::start(&upstream, &args.faults, seed).await?;
+            *dsn = proxy.route(dsn)?;
+            fault_proxies.push(Arc::new(proxy));
+        }
+    }
+    let mut ctx = SuiteContext::new(dsns)
+        .with_seed(seed)
+        .with_keep(args.keep)
+        .with_keep_on_failure(args.keep_on_failure)
+        .with_forensics_dir((!args.no_forensics).then(|| args.forensics_dir.clone()))
+        .with_settings(config.settings(profile)?)
+        .with_settings(args.settings.clone());
+    ctx = ctx.with_retry_policy((&args.retry).into());
+    let shutdown = Shutdown::default().with_grace(args.grace_period);
+    shutdown.listen_for_signals();
+    ctx = ctx.with_shutdown(shutdown.clone());
+    for proxy in &fault_proxies {
+        ctx = ctx.with_fault_proxy(proxy.clone());
+    }
+    ctx = ctx.with_assignments(&args.nodes.assignments)?;
+    // the crashes are drawn from the seed of the data too
+    let crasher = match (args.crash.crash_every, &cluster) {
+        (Some(every), Some(cluster)) => {
+            let crasher = Crasher::new(cluster.clone(), every, args.wait_for_server, seed);
+            Some(Arc::new(crasher))
+        }
+        (Some(_), None) => {
+            return Err(anyhow!(
+                "`--crash-every` requires a cluster started by `--launch`"
+            ));
+        }
+        (None, _) => None,
+    };
+    if let Some(crasher) = &crasher {
+        ctx = ctx.with_crasher(crasher.clone());
+    }
+    if !args.allowed_errors.is_empty() {
+        ctx = ctx.with_allowed_errors(args.allowed_errors.clone());
+    }
+    if let Some(path) = &args.record {
+        info!("recording the statements into {}", path.display());
+        ctx = ctx.with_recorder(Arc::new(Recorder::create(path)?));
+    }
+    let (name, sub_matches) = matches.subcommand().expect("subcommand is required");
+    let runs_suites = name == "run-all" || registry.iter().any(|entry| entry.name == name);
+    if runs_suites && !args.no_preflight {
+        let requirements = registry.iter().flat_map(|entry| entry.requirements);
+        let server = preflight::run(&ctx, args.wait_for_server, requirements).await?;
+        ctx = ctx.with_server(server);
+    }
+    ctx = ctx.with_query_tracking(runs_suites);
+    let cases = if let Some(entry) = registry.iter().find(|entry| entry.name == name) {
+        let fut = entry.start(sub_matches, ctx.clone())?;
+        vec![report::run_case(entry.name.to_owned(), entry.name, fut, &ctx).await]
+    } else {
+        match HarnessCommands::from_arg_matches(&matches)? {
+            HarnessCommands::RunAll(run_all_args) => {
+                run_all::run(run_all_args, &registry, ctx).await?
+            }
+            HarnessCommands::Replay(replay_args) => {
+                let fut = Box::pin(replay::run(replay_args, ctx.clone()));
+                vec![report::run_case("replay".to_owned(), "replay", fut, &ctx).await]
+            }
+            HarnessCommands::Gc(gc_args) => {
+                gc::run(gc_args, &ctx).await?;
+                vec![]
+            }
+        }
+    };
+
+    if let Some(path) = &args.report_junit {
+        report::write_junit(path, &cases)?;
+    }
+    if let Some(path) = &args.report_json {
+        report::write_json(path, &cases)?;
+    }
+
+    for proxy in &fault_proxies {
+        info!("faults injected: {:?}", proxy.injected());
+    }
+    if let Some(crasher) = &crasher {
+        info!("crashes of databend-query: {}", crasher.crashes());
+    }
+    if let Some(cluster) = &cluster {
+        cluster.lock().await.shutdown().await?;
+    }
+    if shutdown.is_requested() {
+        warn!("the run was interrupted, the reports cover the partial run");
+    }
+    let failed = cases.iter().filter(|c| c.failure.is_some()).count();
+    if failed != 0 {
+        return Err(anyhow!("{failed} of {} tests failed", cases.len()));
     }
+    Ok(())
 }
@@ -0,0 +1,286 @@
+//! A scriptable in-process fake of the server, so that the control flow of the suites can be
+//! tested without a Databend, e.g.
+//!
+//! ```ignore
+//! let mock = MockServer::new();
+//! mock.when("select count() from sink", Reply::rows(vec![vec![uint(10)]]));
+//! mock.when("insert into", Reply::error(4001, "conflict").times(3));
+//! mock.when("vacuum", Reply::ok().delay(Duration::from_millis(10)));
+//! let ctx = mock.context();
+//! run_suite(Vacuum2Suite::new(args, ctx.clone()), &ctx).await?;
+//! assert_eq!(mock.count("insert into"), 6);
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use databend_client::PresignedResponse;
+use databend_driver::{
+    Connection, ConnectionInfo, Error, NumberValue, Row, RowIterator, RowStatsIterator,
+    ServerStats, Value,
+};
+use futures_util::stream;
+
+use crate::suite::{Connector, SuiteContext};
+
+/// Reply of the fake server to the statements matching a rule
+#[derive(Clone, Debug)]
+pub struct Reply {
+    outcome: Outcome,
+    delay: Option<Duration>,
+    /// Number of statements the rule still applies to, unlimited if `None`
+    times: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Outcome {
+    Rows(Vec<Vec<Value>>),
+    Error(u16, String),
+    Transport(String),
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Self::rows(vec![])
+    }
+
+    pub fn rows(rows: Vec<Vec<Value>>) -> Self {
+        Self {
+            outcome: Outcome::Rows(rows),
+            delay: None,
+            times: None,
+        }
+    }
+
+    pub fn error(code: u16, message: &str) -> Self {
+        Self {
+            outcome: Outcome::Error(code, message.to_owned()),
+            delay: None,
+            times: None,
+        }
+    }
+
+    /// Fails as if the connection was lost, with a transport error
+    pub fn transport(message: &str) -> Self {
+        Self {
+            outcome: Outcome::Transport(message.to_owned()),
+            delay: None,
+            times: None,
+        }
+    }
+
+    /// Replies after `delay`
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Only applies to the next `n` matching statements
+    pub fn times(mut self, n: usize) -> Self {
+        self.times = Some(n);
+        self
+    }
+}
+
+pub fn uint(n: u64) -> Value {
+    Value::Number(NumberValue::UInt64(n))
+}
+
+pub fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+#[derive(Default)]
+struct State {
+    /// (lowercase pattern, reply), the first rule matching a statement replies
+    rules: Vec<(String, Reply)>,
+    statements: Vec<String>,
+}
+
+/// The fake server, the connections of [`MockServer::context`] share its rules and statement log
+#[derive(Clone, Default)]
+pub struct MockServer {
+    state: Arc<Mutex<State>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies `reply` to the statements containing `pattern`, case insensitive.
+    /// Statements matching no rule succeed with an empty result.
+    pub fn when(&self, pattern: &str, reply: Reply) {
+        let mut state = self.state.lock().unwrap();
+        state.rules.push((pattern.to_lowercase(), reply));
+    }
+
+    pub fn context(&self) -> SuiteContext {
+        SuiteContext::with_connectors(vec![Arc::new(self.clone())])
+    }
+
+    /// All the statements received so far, in order
+    pub fn statements(&self) -> Vec<String> {
+        self.state.lock().unwrap().statements.clone()
+    }
+
+    /// Number of the statements received so far containing `pattern`, case insensitive
+    pub fn count(&self, pattern: &str) -> usize {
+        let pattern = pattern.to_lowercase();
+        let state = self.state.lock().unwrap();
+        state
+            .statements
+            .iter()
+            .filter(|sql| sql.to_lowercase().contains(&pattern))
+            .count()
+    }
+
+    /// Number of the connections opened so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    async fn reply(&self, sql: &str) -> databend_driver::Result<Vec<Vec<Value>>> {
+        let reply = {
+            let mut state = self.state.lock().unwrap();
+            state.statements.push(sql.to_owned());
+            let sql = sql.to_lowercase();
+            let rule = state
+                .rules
+                .iter_mut()
+                .find(|(pattern, reply)| sql.contains(pattern.as_str()) && reply.times != Some(0));
+            rule.map(|(_, reply)| {
+                if let Some(times) = &mut reply.times {
+                    *times -= 1;
+                }
+                reply.clone()
+            })
+        };
+
+        match reply.as_ref().and_then(|r| r.delay) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            // let the other workers run, the suites issue statements in tight loops
+            None => tokio::task::yield_now().await,
+        }
+
+        match reply.map(|r| r.outcome) {
+            None => Ok(vec![]),
+            Some(Outcome::Rows(rows)) => Ok(rows),
+            Some(Outcome::Error(code, message)) => {
+                // the error code type of the client is not exported, but can be deserialized
+                let error_code = serde_json::from_value(serde_json::json!({
+                    "code": code,
+                    "message": message,
+                }))
+                .expect("error code is deserializable");
+                Err(Error::Api(databend_client::Error::QueryFailed(error_code)))
+            }
+            Some(Outcome::Transport(message)) => Err(Error::Transport(message)),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for MockServer {
+    async fn connect(&self) -> Result<Box<dyn Connection>> {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        Ok(Box::new(MockConnection {
+            server: self.clone(),
+        }))
+    }
+}
+
+struct MockConnection {
+    server: MockServer,
+}
+
+fn unsupported<T>(what: &str) -> databend_driver::Result<T> {
+    Err(Error::BadArgument(format!(
+        "{what} is not supported by the mock"
+    )))
+}
+
+#[async_trait]
+impl Connection for MockConnection {
+    async fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            handler: "mock".to_owned(),
+            host: "mock".to_owned(),
+            port: 0,
+            user: "root".to_owned(),
+            database: None,
+            warehouse: None,
+        }
+    }
+
+    async fn exec(&self, sql: &str) -> databend_driver::Result<i64> {
+        let rows = self.server.reply(sql).await?;
+        Ok(rows.len() as i64)
+    }
+
+    async fn query_iter(&self, sql: &str) -> databend_driver::Result<RowIterator> {
+        let rows = self.server.reply(sql).await?;
+        let schema = Arc::default();
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|values| Ok(Row::new(Arc::clone(&schema), values)))
+            .collect();
+        Ok(RowIterator::new(schema, Box::pin(stream::iter(rows))))
+    }
+
+    async fn query_iter_ext(&self, _sql: &str) -> databend_driver::Result<RowStatsIterator> {
+        unsupported("query_iter_ext")
+    }
+
+    async fn get_presigned_url(
+        &self,
+        _operation: &str,
+        _stage: &str,
+    ) -> databend_driver::Result<PresignedResponse> {
+        unsupported("get_presigned_url")
+    }
+
+    async fn upload_to_stage(
+        &self,
+        _stage: &str,
+        _data: databend_driver::conn::Reader,
+        _size: u64,
+    ) -> databend_driver::Result<()> {
+        unsupported("upload_to_stage")
+    }
+
+    async fn load_data(
+        &self,
+        _sql: &str,
+        _data: databend_driver::conn::Reader,
+        _size: u64,
+        _file_format_options: Option<BTreeMap<&str, &str>>,
+        _copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        unsupported("load_data")
+    }
+
+    async fn load_file(
+        &self,
+        _sql: &str,
+        _fp: &Path,
+        _format_options: BTreeMap<&str, &str>,
+        _copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        unsupported("load_file")
+    }
+
+    async fn stream_load(
+        &self,
+        _sql: &str,
+        _data: Vec<Vec<&str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        unsupported("stream_load")
+    }
+}
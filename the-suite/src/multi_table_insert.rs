@@ -1,75 +1,237 @@
-use crate::util::ConnectionExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::load::LoadArgs;
+use crate::nodes::Role;
+use crate::preflight::{Requirement, INSERT_FIRST};
+use crate::suite::{Suite, SuiteContext};
+use crate::util::{read_statements_in, ConnectionExt};
 use anyhow::Result;
-use databend_driver::Client;
+use async_trait::async_trait;
+use clap::Parser;
+use log::info;
 
 const SET_UP: &str = "./sql/multi_table_insert/setup.sql";
 const MULTI_INSERT: &str = "./sql/multi_table_insert/multi_table_insert.sql";
+const TEARDOWN: &str = "./sql/multi_table_insert/teardown.sql";
+/// Runs of the multi table insert, unless `--duration` is given
 const RUN: usize = 100;
+/// Number of the tables `t0`..`t9` of the database of the run the multi table insert writes to
+const TABLES: usize = 10;
+
+/// Multi Table Insert Testing Script
+#[derive(Parser, Clone, Debug, Default)]
+pub struct Args {
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+pub struct MultiTableInsertSuite {
+    args: Args,
+    ctx: SuiteContext,
+    database: String,
+    success: AtomicU64,
+}
+
+#[async_trait]
+impl Suite for MultiTableInsertSuite {
+    type Args = Args;
+    const NAME: &'static str = "multi-table-insert";
+    const DESCRIPTION: &'static str =
+        "`INSERT FIRST` into 10 tables while they are being compacted and re-clustered";
+    const REQUIREMENTS: &'static [Requirement] = &[
+        Requirement::Feature(&INSERT_FIRST),
+        // counts the snapshots of the tables on teardown
+        Requirement::Function("fuse_snapshot"),
+    ];
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("multi_table_insert");
+        Self {
+            args,
+            ctx,
+            database,
+            success: AtomicU64::new(0),
+        }
+    }
+
+    async fn setup(&self) -> Result<()> {
+        let c1 = self.ctx.new_connection().await?;
+        c1.exec(&format!("create or replace database {}", self.database))
+            .await?;
+        c1.exec_lines_in(SET_UP, &self.database).await
+    }
+
+    async fn execute(&self) -> Result<()> {
+        let stats = self.ctx.stats();
+        let stop_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut join_handles = vec![];
+        for i in 0..TABLES {
+            let stop_flag = stop_flag.clone();
+            let ctx = self.ctx.clone();
+            let db = self.database.clone();
+            let stats = stats.clone();
+            let handle: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
+                let c = ctx.new_connection_as(Role::Maintenance).await?;
+                let shutdown = ctx.shutdown();
+                loop {
+                    // the stop flag is not set if the execution is dropped at the end of a
+                    // grace period
+                    if stop_flag.load(std::sync::atomic::Ordering::Acquire)
+                        || shutdown.is_requested()
+                    {
+                        break;
+                    }
+                    let maintenance = [
+                        (
+                            "compact_segment",
+                            format!("optimize table {db}.t{i} compact segment;"),
+                        ),
+                        ("compact", format!("optimize table {db}.t{i} compact;")),
+                        ("purge", format!("optimize table {db}.t{i} purge;")),
+                        ("recluster", format!("alter table {db}.t{i} recluster;")),
+                    ];
+                    for (kind, sql) in maintenance {
+                        // the failures are recorded and classified by the stats, e.g. a conflict
+                        // with the inserts, those not allowed fail the suite once it is done
+                        if let Err(e) = stats.timed(kind, c.exec(&sql)).await {
+                            info!("{kind} of t{i} failed: {e}");
+                        }
+                    }
+                }
+                Ok(())
+            });
+            join_handles.push(handle);
+        }
 
-pub async fn run(dsn: String) -> Result<()> {
-    let client = Client::new(dsn);
-    let c1 = client.get_conn().await?;
-    c1.exec_lines(SET_UP).await?;
-    let stop_flag = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
-    let mut join_handles = vec![];
-    for i in 0..9 {
-        let stop_flag = stop_flag.clone();
-        let client = client.clone();
-        let handle: tokio::task::JoinHandle<Result<()>> = tokio::spawn(async move {
-            let c = client.get_conn().await.unwrap();
-            loop {
-                if stop_flag.load(std::sync::atomic::Ordering::Acquire) {
-                    break;
+        let mut success: u64 = 0;
+        let mut pacer = self
+            .args
+            .load
+            .start(&self.ctx.shutdown())
+            .pacer(Some(RUN as u64));
+        while let Some(scheduled) = pacer.next().await {
+            let i = pacer.done() - 1;
+            let c = self.ctx.new_connection_as(Role::Writer).await?;
+            let result = stats
+                .timed_from(
+                    "multi_table_insert",
+                    scheduled,
+                    c.exec_lines_in(MULTI_INSERT, &self.database),
+                )
+                .await;
+            match result {
+                Ok(_) => {
+                    success += 1;
+                }
+                Err(e) => {
+                    // recorded and classified by the stats, see `Stats::check_errors`
+                    info!("multi table insert {} failed: {:#}", i, e);
                 }
-                c.exec(&format!("optimize table t{} compact segment;", i))
-                    .await?;
-                c.exec(&format!("optimize table t{} compact;", i)).await?;
-                c.exec(&format!("optimize table t{} purge;", i)).await?;
-                c.exec(&format!("alter table t{} recluster;", i)).await?;
             }
-            Ok(())
-        });
-        join_handles.push(handle);
+        }
+
+        stop_flag.store(true, std::sync::atomic::Ordering::Release);
+        for handle in join_handles {
+            handle.await??;
+        }
+
+        println!("success insertions / runs : {}/{}", success, pacer.done());
+        self.success.store(success, Ordering::Relaxed);
+        Ok(())
     }
 
-    let mut success = 0;
-    for i in 0..RUN {
-        let start = std::time::Instant::now();
-        let c = client.get_conn().await?;
-        match c.exec_lines(MULTI_INSERT).await {
-            Ok(_) => {
-                success += 1;
-            }
-            Err(e) => {
-                println!("multi table insert {} failed: {:?}", i, e);
-            }
+    async fn verify(&self) -> Result<()> {
+        let success = self.success.load(Ordering::Relaxed);
+        let db = &self.database;
+        for i in 0..TABLES {
+            let c = self.ctx.new_connection().await?;
+            println!("verify {}", i);
+            c.assert_query(
+                &format!("SELECT count(*) FROM {db}.t{i} WHERE c % 10 <> {i};"),
+                vec![(0,)],
+            )
+            .await?;
+            c.assert_query(
+                &format!("SELECT count(*) FROM {db}.t{i};"),
+                vec![(success * 10000 / 10,)],
+            )
+            .await?;
         }
-        println!("multi table insert {} cost {:?}", i, start.elapsed());
+
+        println!("---All tests passed!---");
+
+        Ok(())
     }
 
-    stop_flag.store(true, std::sync::atomic::Ordering::Release);
-    for handle in join_handles {
-        handle.await??;
+    /// Logs the number of snapshots of each table, before its database is dropped
+    async fn teardown(&self) -> Result<()> {
+        let c = self.ctx.new_connection().await?;
+        for statement in read_statements_in(TEARDOWN, &self.database)? {
+            for row in c.query_strings(&statement.sql).await? {
+                info!("snapshots of {}", row.join(": "));
+            }
+        }
+        Ok(())
     }
+}
 
-    println!("success insertions / runs : {}/{}", success, RUN);
-    // verify
-    for i in 0..10 {
-        let c = client.get_conn().await?;
-        println!("verify {}", i);
-        c.assert_query(
-            &format!("SELECT count(*) FROM t{} WHERE c % 10 <> {};", i, i),
-            vec![(0,)],
-        )
-        .await;
-        c.assert_query(
-            &format!("SELECT count(*) FROM t{};", i),
-            vec![(success * 10000 / 10,)],
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::{uint, MockServer, Reply};
+    use crate::suite::run_suite;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn maintains_every_table() {
+        let mock = MockServer::new();
+        // give the maintenance workers the time to start
+        mock.when("INSERT FIRST", Reply::ok().delay(Duration::from_millis(1)));
+        mock.when("WHERE c %", Reply::rows(vec![vec![uint(0)]]));
+        mock.when(
+            "SELECT count(*) FROM",
+            Reply::rows(vec![vec![uint(RUN as u64 * 1000)]]),
+        );
+        let ctx = mock.context();
+        run_suite(
+            MultiTableInsertSuite::new(Args::default(), ctx.clone()),
+            &ctx,
         )
-        .await;
+        .await
+        .unwrap();
+
+        let db = ctx.database("multi_table_insert");
+        assert_eq!(
+            mock.count(&format!("create or replace table {db}.t")),
+            TABLES
+        );
+        assert_eq!(mock.count(&format!("INTO {db}.t9")), RUN);
+        for i in 0..TABLES {
+            assert!(mock.count(&format!("optimize table {db}.t{i} compact segment")) > 0);
+        }
+        assert_eq!(mock.count(&format!("DROP DATABASE IF EXISTS {db}")), 1);
+        assert_eq!(
+            ctx.stats().snapshot()["multi_table_insert"].success,
+            RUN as u64
+        );
     }
 
-    println!("---All tests passed!---");
+    #[tokio::test(flavor = "multi_thread")]
+    async fn missing_rows_fail_the_suite_and_drop_the_database() {
+        let mock = MockServer::new();
+        mock.when("WHERE c %", Reply::rows(vec![vec![uint(0)]]));
+        mock.when("SELECT count(*) FROM", Reply::rows(vec![vec![uint(1)]]));
+        let ctx = mock.context();
+        let suite = MultiTableInsertSuite::new(Args::default(), ctx.clone());
+        let result = run_suite(suite, &ctx).await;
 
-    Ok(())
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(
+            error.contains("unexpected result of [SELECT count(*) FROM"),
+            "{error}"
+        );
+        let db = ctx.database("multi_table_insert");
+        assert_eq!(mock.count(&format!("DROP DATABASE IF EXISTS {db}")), 1);
+    }
 }
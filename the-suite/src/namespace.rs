@@ -0,0 +1,62 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rand::Rng;
+
+/// Prefix of the names of the databases created by the suites
+pub const PREFIX: &str = "ts_";
+
+/// Fixed databases of the suites from before the runs were namespaced
+pub const LEGACY_DATABASES: &[&str] = &[
+    "test_stream",
+    "test_vacuum2",
+    "test_vacuum",
+    "auto_vacuum",
+    "test_txn",
+    "test_merge_into",
+    "test_replace_into",
+];
+
+const RUN_TIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Id of a suite run, e.g. `20250101_120000_3fa2c1`, which namespaces the databases of the run so
+/// that concurrent runs on the same server do not step on each other
+pub fn new_run_id() -> String {
+    format!(
+        "{}_{:06x}",
+        Utc::now().format(RUN_TIME_FORMAT),
+        rand::thread_rng().gen_range(0..0x100_0000)
+    )
+}
+
+/// Name of the database `name` of the run `run_id`, e.g. `ts_20250101_120000_3fa2c1_test_stream`
+pub fn database_name(run_id: &str, name: &str) -> String {
+    format!("{PREFIX}{run_id}_{name}")
+}
+
+/// When the run of a database named by [`database_name`] started, `None` for other databases
+pub fn run_started_at(database: &str) -> Option<DateTime<Utc>> {
+    let run_id = database.strip_prefix(PREFIX)?;
+    let time = run_id.get(.."20250101_120000".len())?;
+    let time = NaiveDateTime::parse_from_str(time, RUN_TIME_FORMAT).ok()?;
+    Some(time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn databases_of_a_run() {
+        let run_id = new_run_id();
+        assert_eq!(run_id.len(), "20250101_120000_3fa2c1".len());
+        assert_ne!(run_id, new_run_id());
+        assert_eq!(
+            database_name("20250101_120000_3fa2c1", "test_stream"),
+            "ts_20250101_120000_3fa2c1_test_stream"
+        );
+        let started_at = run_started_at("ts_20250101_120000_3fa2c1_test_stream").unwrap();
+        assert_eq!(started_at.to_rfc3339(), "2025-01-01T12:00:00+00:00");
+        assert!(run_started_at(&database_name(&run_id, "t")).unwrap() <= Utc::now());
+        assert_eq!(run_started_at("ts_tmp"), None);
+        assert_eq!(run_started_at("test_stream"), None);
+    }
+}
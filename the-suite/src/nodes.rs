@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, ValueEnum};
+use databend_driver::Connection;
+use log::{info, warn};
+
+use crate::fault_proxy;
+use crate::suite::Connector;
+use crate::util::ConnectionExt;
+
+/// The query nodes of a cluster the connections are spread over, see [`Nodes`]
+#[derive(Args, Clone, Debug)]
+pub struct NodeArgs {
+    /// Run against every query node of the cluster of the DSN, read from its `system.clusters`.
+    /// The nodes are reached on their hosts, on the port of the DSN
+    #[arg(long, global = true)]
+    pub discover_nodes: bool,
+
+    /// Open the connections of the workers of ROLE on NODE, the position of its DSN from 1,
+    /// instead of spreading them round-robin over the nodes
+    #[arg(long = "assign", global = true, value_name = "ROLE=NODE", value_parser = parse_assignment)]
+    pub assignments: Vec<(Role, usize)>,
+}
+
+/// What the workers of a suite do to its tables, to run them on nodes of their own
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Inserts, updates or upserts rows, e.g. `merge into`
+    Writer,
+    /// Compacts, purges, re-clusters or vacuums the tables, e.g. `optimize table ... compact`
+    Maintenance,
+}
+
+fn parse_assignment(s: &str) -> Result<(Role, usize)> {
+    let (role, node) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("invalid assignment `{s}`, expected `ROLE=NODE`"))?;
+    let role = Role::from_str(role, true).map_err(|e| anyhow!("invalid role `{role}`: {e}"))?;
+    match node.parse() {
+        Ok(node) if node > 0 => Ok((role, node)),
+        _ => Err(anyhow!(
+            "invalid node `{node}`, expected its position from 1"
+        )),
+    }
+}
+
+/// Connectors of the query nodes. The connections of the workers of an assigned role are opened on
+/// its node, the others on the nodes in turn
+pub struct Nodes {
+    connectors: Vec<Arc<dyn Connector>>,
+    /// Index of the node of each assigned role
+    assignments: BTreeMap<Role, usize>,
+    next: AtomicUsize,
+}
+
+impl Nodes {
+    pub fn new(connectors: Vec<Arc<dyn Connector>>) -> Self {
+        assert!(!connectors.is_empty(), "no query node");
+        Self {
+            connectors,
+            assignments: BTreeMap::new(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The same nodes, with the roles assigned to them by their positions from 1
+    pub fn with_assignments(&self, assignments: &[(Role, usize)]) -> Result<Self> {
+        let mut nodes = Self {
+            connectors: self.connectors.clone(),
+            assignments: self.assignments.clone(),
+            next: AtomicUsize::new(0),
+        };
+        for &(role, node) in assignments {
+            if node == 0 || node > nodes.connectors.len() {
+                return Err(anyhow!(
+                    "cannot assign {role:?} to node {node}, of {} nodes",
+                    nodes.connectors.len()
+                ));
+            }
+            nodes.assignments.insert(role, node - 1);
+        }
+        Ok(nodes)
+    }
+
+    /// The same nodes and assignments, through the connectors returned by `wrap`
+    pub fn map(&self, wrap: impl Fn(Arc<dyn Connector>) -> Arc<dyn Connector>) -> Self {
+        Self {
+            connectors: self.connectors.iter().cloned().map(wrap).collect(),
+            assignments: self.assignments.clone(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn connectors(&self) -> &[Arc<dyn Connector>] {
+        &self.connectors
+    }
+
+    /// Connector of the next connection of a worker of `role`, if any
+    pub fn connector(&self, role: Option<Role>) -> &Arc<dyn Connector> {
+        let node = match role.and_then(|role| self.assignments.get(&role)) {
+            Some(&node) => node,
+            None => self.next.fetch_add(1, Ordering::Relaxed) % self.connectors.len(),
+        };
+        &self.connectors[node]
+    }
+}
+
+/// DSNs of the query nodes of the cluster `conn` is connected to, from its `system.clusters`:
+/// `dsn` with the host of each node, which is expected to listen on the port of `dsn`
+pub async fn discover(conn: &dyn Connection, dsn: &str) -> Result<Vec<String>> {
+    let rows = conn
+        .query_strings("SELECT name, host FROM system.clusters ORDER BY name")
+        .await
+        .context("failed to read system.clusters")?;
+    let hosts: Vec<&str> = rows
+        .iter()
+        .filter_map(|row| row.get(1))
+        .map(String::as_str)
+        .collect();
+    let dsns = node_dsns(dsn, &hosts)?;
+    if dsns.len() < hosts.len() {
+        warn!(
+            "{} query nodes share {} hosts, pass the DSN of each node instead",
+            hosts.len(),
+            dsns.len()
+        );
+    }
+    info!("discovered the query nodes {}", dsns.join(", "));
+    Ok(dsns)
+}
+
+/// `dsn` with each of the `hosts`, once, keeping its port
+fn node_dsns(dsn: &str, hosts: &[&str]) -> Result<Vec<String>> {
+    let (prefix, _, path) = fault_proxy::split_dsn(dsn)?;
+    let upstream = fault_proxy::upstream(dsn)?;
+    let (_, port) = upstream.rsplit_once(':').expect("upstream has a port");
+    let mut dsns: Vec<String> = vec![];
+    for host in hosts {
+        let node = format!("{prefix}{host}:{port}{path}");
+        if !dsns.contains(&node) {
+            dsns.push(node);
+        }
+    }
+    if dsns.is_empty() {
+        return Err(anyhow!("no query node in system.clusters"));
+    }
+    Ok(dsns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, MockServer, Reply};
+    use crate::suite::SuiteContext;
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            parse_assignment("maintenance=2").unwrap(),
+            (Role::Maintenance, 2)
+        );
+        assert!(parse_assignment("writer=0").is_err());
+        assert!(parse_assignment("reader=1").is_err());
+        assert!(parse_assignment("writer").is_err());
+    }
+
+    #[tokio::test]
+    async fn discovers_the_nodes_of_the_cluster() {
+        let mock = MockServer::new();
+        mock.when(
+            "FROM system.clusters",
+            Reply::rows(vec![
+                vec![string("n1"), string("10.0.0.1")],
+                vec![string("n2"), string("10.0.0.2")],
+                vec![string("n3"), string("10.0.0.2")],
+            ]),
+        );
+        let conn = mock.context().new_connection().await.unwrap();
+        let dsn = "databend://root:@db.local:8001/default?sslmode=disable";
+        assert_eq!(
+            discover(&*conn, dsn).await.unwrap(),
+            [
+                "databend://root:@10.0.0.1:8001/default?sslmode=disable",
+                "databend://root:@10.0.0.2:8001/default?sslmode=disable",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn spreads_the_connections_over_the_nodes() {
+        let (a, b) = (MockServer::new(), MockServer::new());
+        let connectors: Vec<Arc<dyn Connector>> = vec![Arc::new(a.clone()), Arc::new(b.clone())];
+        let ctx = SuiteContext::with_connectors(connectors)
+            .with_assignments(&[(Role::Maintenance, 2)])
+            .unwrap();
+        for _ in 0..4 {
+            let conn = ctx.new_connection().await.unwrap();
+            conn.exec("insert into t values(1)").await.unwrap();
+        }
+        let conn = ctx.new_connection_as(Role::Maintenance).await.unwrap();
+        conn.exec("optimize table t compact").await.unwrap();
+        let conn = ctx.new_connection_as(Role::Writer).await.unwrap();
+        conn.exec("merge into t").await.unwrap();
+
+        assert_eq!(a.count("insert into"), 2);
+        assert_eq!(b.count("insert into"), 2);
+        assert_eq!(b.count("optimize table"), 1);
+        assert_eq!(a.count("merge into") + b.count("merge into"), 1);
+        assert!(ctx.clone().with_assignments(&[(Role::Writer, 3)]).is_err());
+    }
+}
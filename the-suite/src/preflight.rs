@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use databend_driver::Connection;
+use log::{info, warn};
+use tokio::time::Instant;
+
+use crate::namespace;
+use crate::suite::SuiteContext;
+use crate::util::{ConnectionExt, DATABASE_PLACEHOLDER};
+
+/// What a suite needs from the server, the harness skips the suite on a server lacking it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Requirement {
+    /// A setting of `system.settings`, e.g. `enable_auto_vacuum`
+    Setting(&'static str),
+    /// A function of `system.functions` or `system.table_functions`, e.g. `fuse_snapshot`
+    Function(&'static str),
+    /// A feature that can only be detected by using it
+    Feature(&'static Feature),
+}
+
+/// A feature of the server, detected by running its probe in a scratch database
+#[derive(Debug, PartialEq)]
+pub struct Feature {
+    pub name: &'static str,
+    /// Statements that succeed on a server with the feature, `${DATABASE}` is the scratch database
+    pub probe: &'static [&'static str],
+}
+
+pub const CHANGE_TRACKING: Feature = Feature {
+    name: "change tracking",
+    probe: &[
+        "CREATE TABLE ${DATABASE}.change_tracking(c INT)",
+        "ALTER TABLE ${DATABASE}.change_tracking SET OPTIONS(change_tracking = true)",
+        "CREATE STREAM ${DATABASE}.change_tracking_stream ON TABLE ${DATABASE}.change_tracking",
+    ],
+};
+
+pub const INSERT_FIRST: Feature = Feature {
+    name: "INSERT FIRST",
+    probe: &[
+        "CREATE TABLE ${DATABASE}.insert_first(c INT)",
+        "INSERT FIRST WHEN c > 0 THEN INTO ${DATABASE}.insert_first SELECT 1 AS c",
+    ],
+};
+
+pub const FUSE_VACUUM2: Feature = Feature {
+    name: "system$fuse_vacuum2",
+    probe: &[
+        "CREATE TABLE ${DATABASE}.fuse_vacuum2(c INT)",
+        "CALL system$fuse_vacuum2('${DATABASE}', 'fuse_vacuum2')",
+    ],
+};
+
+/// What the server under test offers, read by [`run`]
+#[derive(Debug, Default)]
+pub struct ServerInfo {
+    pub version: String,
+    settings: BTreeSet<String>,
+    functions: BTreeSet<String>,
+    /// Probed features, with the error of the probe of those missing
+    features: BTreeMap<&'static str, Option<String>>,
+}
+
+impl ServerInfo {
+    /// Why a suite of `requirements` cannot run on this server, `None` if it can
+    pub fn missing(&self, requirements: &[Requirement]) -> Option<String> {
+        let missing: Vec<String> = requirements
+            .iter()
+            .filter_map(|requirement| match requirement {
+                Requirement::Setting(name) if !self.settings.contains(*name) => {
+                    Some(format!("setting `{name}`"))
+                }
+                Requirement::Function(name) if !self.functions.contains(*name) => {
+                    Some(format!("function `{name}`"))
+                }
+                Requirement::Feature(feature) => match self.features.get(feature.name) {
+                    Some(Some(e)) => Some(format!("{} ({e})", feature.name)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        if missing.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "server {} lacks {}",
+            self.version,
+            missing.join(", ")
+        ))
+    }
+}
+
+/// Waits up to `wait` for the server to accept connections, then reads its version, settings and
+/// functions, checks that the user can create databases and probes the features of `requirements`
+pub async fn run<'a>(
+    ctx: &SuiteContext,
+    wait: Duration,
+    requirements: impl IntoIterator<Item = &'a Requirement>,
+) -> Result<ServerInfo> {
+    let (conn, version) = wait_for_server(ctx, wait).await?;
+    info!("server version {version}");
+
+    let names = |rows: Vec<Vec<String>>| rows.into_iter().filter_map(|row| row.into_iter().next());
+    let settings = conn
+        .query_strings("SELECT name FROM system.settings")
+        .await
+        .context("failed to read the settings of the server")?;
+    let mut functions = BTreeSet::new();
+    for table in ["system.functions", "system.table_functions"] {
+        let rows = conn
+            .query_strings(&format!("SELECT name FROM {table}"))
+            .await
+            .with_context(|| format!("failed to read {table}"))?;
+        functions.extend(names(rows));
+    }
+    let mut server = ServerInfo {
+        version,
+        settings: names(settings).collect(),
+        functions,
+        features: BTreeMap::new(),
+    };
+
+    let scratch = namespace::database_name(ctx.run_id(), "preflight");
+    conn.exec(&format!("CREATE DATABASE {scratch}"))
+        .await
+        .context("the user cannot create databases, which the suites create their tables in")?;
+    for requirement in requirements {
+        let Requirement::Feature(feature) = requirement else {
+            continue;
+        };
+        if server.features.contains_key(feature.name) {
+            continue;
+        }
+        let error = probe(&*conn, feature, &scratch)
+            .await
+            .err()
+            .map(|e| format!("{e:#}"));
+        if let Some(e) = &error {
+            warn!("server lacks {}: {e}", feature.name);
+        }
+        server.features.insert(feature.name, error);
+    }
+    conn.exec(&format!("DROP DATABASE IF EXISTS {scratch}"))
+        .await
+        .context("the user cannot drop databases, which the suites drop once they are done")?;
+    Ok(server)
+}
+
+/// Connects until `SELECT version()` succeeds, with a backoff doubled on each attempt
+async fn wait_for_server(
+    ctx: &SuiteContext,
+    wait: Duration,
+) -> Result<(Box<dyn Connection>, String)> {
+    let deadline = Instant::now() + wait;
+    let mut backoff = Duration::from_millis(100);
+    loop {
+        let result = async {
+            let conn = ctx.new_connection().await?;
+            let version = conn
+                .query_strings("SELECT version()")
+                .await?
+                .into_iter()
+                .flatten()
+                .next()
+                .ok_or_else(|| anyhow!("no version returned"))?;
+            Ok::<_, anyhow::Error>((conn, version))
+        }
+        .await;
+        match result {
+            Ok(ready) => return Ok(ready),
+            Err(e) if Instant::now() + backoff < deadline => {
+                info!("server not ready, retrying in {backoff:?}: {e:#}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(5));
+            }
+            Err(e) => return Err(e.context(format!("server not ready within {wait:?}"))),
+        }
+    }
+}
+
+async fn probe(conn: &dyn Connection, feature: &Feature, database: &str) -> Result<()> {
+    for sql in feature.probe {
+        conn.exec(&sql.replace(DATABASE_PLACEHOLDER, database))
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, MockServer, Reply};
+    use crate::suite::{registry, Skipped};
+
+    fn names(names: &[&str]) -> Reply {
+        Reply::rows(names.iter().map(|name| vec![string(name)]).collect())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_the_server_and_probes_the_features() {
+        let mock = MockServer::new();
+        mock.when("version()", Reply::error(1001, "starting").times(3));
+        mock.when("version()", names(&["v1.2.700-nightly"]));
+        mock.when("FROM system.settings", names(&["max_threads"]));
+        mock.when("FROM system.table_functions", names(&["fuse_snapshot"]));
+        mock.when("INSERT FIRST", Reply::error(1005, "syntax error"));
+        let ctx = mock.context();
+
+        let start = tokio::time::Instant::now();
+        let requirements = [
+            Requirement::Feature(&INSERT_FIRST),
+            Requirement::Feature(&CHANGE_TRACKING),
+        ];
+        let server = run(&ctx, Duration::from_secs(60), &requirements)
+            .await
+            .unwrap();
+        // 100ms + 200ms + 400ms of backoff
+        assert_eq!(start.elapsed(), Duration::from_millis(700));
+        assert_eq!(server.version, "v1.2.700-nightly");
+        assert_eq!(mock.count("CREATE STREAM"), 1);
+        assert_eq!(mock.count("DROP DATABASE"), 1);
+
+        assert_eq!(
+            server.missing(&[Requirement::Function("fuse_snapshot")]),
+            None
+        );
+        assert_eq!(
+            server.missing(&[Requirement::Feature(&CHANGE_TRACKING)]),
+            None
+        );
+        let reason = server
+            .missing(&[
+                Requirement::Setting("enable_auto_vacuum"),
+                Requirement::Feature(&INSERT_FIRST),
+            ])
+            .unwrap();
+        let lacks = "server v1.2.700-nightly lacks setting `enable_auto_vacuum`, INSERT FIRST (";
+        assert!(reason.starts_with(lacks), "{reason}");
+
+        // the suites requiring what the server lacks are skipped
+        let ctx = ctx.with_server(server);
+        let entry = registry()
+            .into_iter()
+            .find(|e| e.name == "auto-vacuum")
+            .unwrap();
+        let matches = entry.command().get_matches_from(["auto-vacuum"]);
+        let error = entry.start(&matches, ctx).unwrap().await.unwrap_err();
+        assert!(error.downcast_ref::<Skipped>().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_on_a_server_never_ready() {
+        let mock = MockServer::new();
+        mock.when("version()", Reply::error(1001, "starting"));
+        let error = run(&mock.context(), Duration::from_secs(10), [])
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("server not ready within 10s"));
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use databend_client::PresignedResponse;
+use databend_driver::conn::Reader;
+use databend_driver::{Connection, ConnectionInfo, RowIterator, RowStatsIterator, ServerStats};
+use log::warn;
+use serde::Serialize;
+
+use crate::namespace;
+use crate::util::ConnectionExt;
+
+/// Tags every statement of a run with a query id of its own, as a leading comment kept in the
+/// `query_text` of `system.query_log`, and remembers how the statement ended on the client, so that
+/// both ends can be reconciled once the run is done, see [`QueryTracker::reconcile`]
+pub struct QueryTracker {
+    /// Prefix of the query ids, e.g. `ts_20250101_120000_3fa2c1_q`
+    prefix: String,
+    next: AtomicU64,
+    /// Client outcome of every statement by sequence number
+    outcomes: Mutex<BTreeMap<u64, ClientOutcome>>,
+}
+
+enum ClientOutcome {
+    Success,
+    Failure { sql: String, error: String },
+}
+
+/// How the outcomes of the statements of a run on the client and on the server compare
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    /// Statements issued by the run
+    pub statements: u64,
+    /// Statements that succeeded or failed on both ends
+    pub agreed: u64,
+    /// Statements not in the query log, e.g. failed before reaching the server
+    pub unlogged: u64,
+    /// Query ids of the statements that succeeded on the client but failed on the server
+    pub client_success_server_failure: Vec<String>,
+    /// Statements that failed on the client but were committed by the server, e.g. on a
+    /// connection lost while waiting for the response
+    pub ambiguous_commits: Vec<AmbiguousCommit>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AmbiguousCommit {
+    pub query_id: String,
+    pub sql: String,
+    pub client_error: String,
+}
+
+impl QueryTracker {
+    pub fn new(run_id: &str) -> Self {
+        Self {
+            prefix: format!("{}q", namespace::database_name(run_id, "")),
+            next: AtomicU64::new(0),
+            outcomes: Mutex::default(),
+        }
+    }
+
+    /// Wraps `conn`, tagging its statements
+    pub fn track(self: &Arc<Self>, conn: Box<dyn Connection>) -> Box<dyn Connection> {
+        Box::new(TrackedConnection {
+            inner: conn,
+            tracker: self.clone(),
+        })
+    }
+
+    fn query_id(&self, seq: u64) -> String {
+        format!("{}{seq}", self.prefix)
+    }
+
+    fn tag(&self, sql: &str) -> (u64, String) {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        (seq, format!("/* {} */ {sql}", self.query_id(seq)))
+    }
+
+    fn record<T>(&self, seq: u64, sql: &str, result: &databend_driver::Result<T>) {
+        let outcome = match result {
+            Ok(_) => ClientOutcome::Success,
+            Err(e) => ClientOutcome::Failure {
+                sql: sql.to_owned(),
+                error: e.to_string(),
+            },
+        };
+        self.outcomes.lock().unwrap().insert(seq, outcome);
+    }
+
+    /// Looks the statements issued so far up in `system.query_log` of the nodes of `conns` and
+    /// compares their outcomes on the server with those on the client. The statements still
+    /// running, or not flushed to the query log yet, count as unlogged.
+    pub async fn reconcile(&self, conns: &[Box<dyn Connection>]) -> Result<Reconciliation> {
+        // not the lookup itself, if `conn` is tracked
+        let issued = self.next.load(Ordering::Relaxed);
+        let sql = format!(
+            "SELECT query_text, log_type_name, exception_code FROM system.query_log \
+             WHERE query_text LIKE '/* {}%' AND log_type_name <> 'Start'",
+            self.prefix
+        );
+        // committed if any of its entries says so
+        let mut server: BTreeMap<u64, bool> = BTreeMap::new();
+        for conn in conns {
+            for row in conn.query_strings(&sql).await? {
+                let [text, log_type, code] = row.as_slice() else {
+                    continue;
+                };
+                let seq = untag(text)
+                    .0
+                    .and_then(|query_id| query_id.strip_prefix(&self.prefix))
+                    .and_then(|seq| seq.parse().ok());
+                if let Some(seq) = seq {
+                    let committed = log_type == "Finish" && code == "0";
+                    *server.entry(seq).or_default() |= committed;
+                }
+            }
+        }
+
+        let outcomes = self.outcomes.lock().unwrap();
+        let mut reconciliation = Reconciliation::default();
+        for (seq, client) in outcomes.range(..issued) {
+            reconciliation.statements += 1;
+            match (client, server.get(seq)) {
+                (_, None) => reconciliation.unlogged += 1,
+                (ClientOutcome::Success, Some(true))
+                | (ClientOutcome::Failure { .. }, Some(false)) => reconciliation.agreed += 1,
+                (ClientOutcome::Success, Some(false)) => reconciliation
+                    .client_success_server_failure
+                    .push(self.query_id(*seq)),
+                (ClientOutcome::Failure { sql, error }, Some(true)) => {
+                    reconciliation.ambiguous_commits.push(AmbiguousCommit {
+                        query_id: self.query_id(*seq),
+                        sql: sql.clone(),
+                        client_error: error.clone(),
+                    })
+                }
+            }
+        }
+        Ok(reconciliation)
+    }
+}
+
+/// The query id a statement was tagged with by a [`QueryTracker`], if any, and the statement
+/// without its tag
+pub fn untag(sql: &str) -> (Option<&str>, &str) {
+    let tagged = sql
+        .strip_prefix("/* ")
+        .and_then(|sql| sql.split_once(" */ "));
+    match tagged {
+        Some((query_id, sql)) => (Some(query_id), sql),
+        None => (None, sql),
+    }
+}
+
+impl Reconciliation {
+    /// Warns about the statements whose outcomes disagree
+    pub fn log(&self, name: &str) {
+        for commit in &self.ambiguous_commits {
+            warn!(
+                "[{name}] ambiguous commit {}: failed on the client ({}) but committed: {}",
+                commit.query_id, commit.client_error, commit.sql
+            );
+        }
+        if !self.client_success_server_failure.is_empty() {
+            warn!(
+                "[{name}] succeeded on the client but failed on the server: {}",
+                self.client_success_server_failure.join(", ")
+            );
+        }
+    }
+}
+
+struct TrackedConnection {
+    inner: Box<dyn Connection>,
+    tracker: Arc<QueryTracker>,
+}
+
+#[async_trait]
+impl Connection for TrackedConnection {
+    async fn info(&self) -> ConnectionInfo {
+        self.inner.info().await
+    }
+
+    async fn close(&self) -> databend_driver::Result<()> {
+        self.inner.close().await
+    }
+
+    async fn exec(&self, sql: &str) -> databend_driver::Result<i64> {
+        let (seq, tagged) = self.tracker.tag(sql);
+        let result = self.inner.exec(&tagged).await;
+        self.tracker.record(seq, sql, &result);
+        result
+    }
+
+    /// The statement succeeded on the client once its first response is received,
+    /// errors while fetching its rows are not tracked
+    async fn query_iter(&self, sql: &str) -> databend_driver::Result<RowIterator> {
+        let (seq, tagged) = self.tracker.tag(sql);
+        let result = self.inner.query_iter(&tagged).await;
+        self.tracker.record(seq, sql, &result);
+        result
+    }
+
+    async fn query_iter_ext(&self, sql: &str) -> databend_driver::Result<RowStatsIterator> {
+        let (seq, tagged) = self.tracker.tag(sql);
+        let result = self.inner.query_iter_ext(&tagged).await;
+        self.tracker.record(seq, sql, &result);
+        result
+    }
+
+    async fn get_presigned_url(
+        &self,
+        operation: &str,
+        stage: &str,
+    ) -> databend_driver::Result<PresignedResponse> {
+        self.inner.get_presigned_url(operation, stage).await
+    }
+
+    async fn upload_to_stage(
+        &self,
+        stage: &str,
+        data: Reader,
+        size: u64,
+    ) -> databend_driver::Result<()> {
+        self.inner.upload_to_stage(stage, data, size).await
+    }
+
+    async fn load_data(
+        &self,
+        sql: &str,
+        data: Reader,
+        size: u64,
+        file_format_options: Option<BTreeMap<&str, &str>>,
+        copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner
+            .load_data(sql, data, size, file_format_options, copy_options)
+            .await
+    }
+
+    async fn load_file(
+        &self,
+        sql: &str,
+        fp: &Path,
+        format_options: BTreeMap<&str, &str>,
+        copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner
+            .load_file(sql, fp, format_options, copy_options)
+            .await
+    }
+
+    async fn stream_load(
+        &self,
+        sql: &str,
+        data: Vec<Vec<&str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner.stream_load(sql, data).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, MockServer, Reply};
+
+    #[tokio::test]
+    async fn reconciles_the_outcomes_of_both_ends() {
+        let mock = MockServer::new();
+        mock.when(
+            "merge into",
+            Reply::error(1001, "connection reset").times(1),
+        );
+        mock.when("replace into", Reply::error(4001, "conflict").times(1));
+        let ctx = mock.context().with_query_tracking(true);
+        let conn = ctx.new_connection().await.unwrap();
+        // q0 succeeds on both ends, q1 is an ambiguous commit, q2 failed on both ends,
+        // q3 failed on the server only, q4 is not in the query log
+        let statements = [
+            "insert into t values(1)",
+            "merge into t",
+            "replace into t",
+            "delete from t",
+            "update t",
+        ];
+        for sql in statements {
+            let _ = conn.exec(sql).await;
+        }
+        let query_id = |seq: u64| format!("ts_{}_q{seq}", ctx.run_id());
+        let log = |seq: u64, log_type: &str, code: &str| {
+            let text = format!("/* {} */ ...", query_id(seq));
+            vec![string(&text), string(log_type), string(code)]
+        };
+        mock.when(
+            "FROM system.query_log",
+            Reply::rows(vec![
+                log(0, "Finish", "0"),
+                log(1, "Finish", "0"),
+                log(2, "Error", "4001"),
+                log(3, "Error", "1006"),
+            ]),
+        );
+
+        let statements = mock.statements();
+        assert_eq!(statements[1], format!("/* {} */ merge into t", query_id(1)));
+        let tracker = ctx.query_tracker().unwrap();
+        let reconciliation = tracker.reconcile(&[conn]).await.unwrap();
+        assert_eq!(
+            reconciliation,
+            Reconciliation {
+                statements: 5,
+                agreed: 2,
+                unlogged: 1,
+                client_success_server_failure: vec![query_id(3)],
+                ambiguous_commits: vec![AmbiguousCommit {
+                    query_id: query_id(1),
+                    sql: "merge into t".to_owned(),
+                    client_error: reconciliation.ambiguous_commits[0].client_error.clone(),
+                }],
+            }
+        );
+        assert!(reconciliation.ambiguous_commits[0]
+            .client_error
+            .contains("connection reset"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use databend_client::PresignedResponse;
+use databend_driver::conn::Reader;
+use databend_driver::{Connection, ConnectionInfo, RowIterator, RowStatsIterator, ServerStats};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::reconcile;
+use crate::suite::Connector;
+
+/// A statement of the trace, one JSON object per line of the trace file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Id of the tokio task that issued the statement
+    pub worker: String,
+    /// Connections are numbered in the order they are opened
+    pub session: u64,
+    /// Microseconds since the unix epoch
+    pub start_us: u64,
+    pub end_us: u64,
+    pub sql: String,
+    /// `None` if the statement succeeded
+    pub error: Option<String>,
+    /// Id the statement was tagged with by the query tracker, without the tag in `sql`
+    pub query_id: Option<String>,
+}
+
+impl TraceRecord {
+    pub fn load_all(path: &Path) -> Result<Vec<Self>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read trace {}", path.display()))?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("invalid record at {}:{}", path.display(), idx + 1))
+            })
+            .collect()
+    }
+}
+
+/// Appends the statements of the recorded connections to the trace file
+pub struct Recorder {
+    file: Mutex<File>,
+    sessions: AtomicU64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create trace {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+            sessions: AtomicU64::new(0),
+        })
+    }
+
+    fn record(&self, record: &TraceRecord) {
+        let mut line = serde_json::to_string(record).expect("trace record is serializable");
+        line.push('\n');
+        // a failed write must not fail the suite, the trace is a diagnostic
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::warn!("failed to write the trace: {e}");
+        }
+    }
+}
+
+/// Wraps the connections of another connector, recording all their statements
+pub struct RecordingConnector {
+    inner: Arc<dyn Connector>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingConnector {
+    pub fn new(inner: Arc<dyn Connector>, recorder: Arc<Recorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl Connector for RecordingConnector {
+    async fn connect(&self) -> Result<Box<dyn Connection>> {
+        let inner = self.inner.connect().await?;
+        let session = self.recorder.sessions.fetch_add(1, Ordering::Relaxed);
+        Ok(Box::new(RecordingConnection {
+            inner,
+            session,
+            recorder: self.recorder.clone(),
+        }))
+    }
+}
+
+struct RecordingConnection {
+    inner: Box<dyn Connection>,
+    session: u64,
+    recorder: Arc<Recorder>,
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+impl RecordingConnection {
+    /// Records `sql` with the query id it was tagged with by the query tracker, if any
+    fn record(&self, sql: &str, start_us: u64, error: Option<String>) {
+        let end_us = now_us();
+        let (query_id, sql) = reconcile::untag(sql);
+        self.recorder.record(&TraceRecord {
+            worker: tokio::task::try_id().map_or_else(|| "main".to_owned(), |id| id.to_string()),
+            session: self.session,
+            start_us,
+            end_us,
+            sql: sql.to_owned(),
+            error,
+            query_id: query_id.map(str::to_owned),
+        });
+    }
+}
+
+#[async_trait]
+impl Connection for RecordingConnection {
+    async fn info(&self) -> ConnectionInfo {
+        self.inner.info().await
+    }
+
+    async fn close(&self) -> databend_driver::Result<()> {
+        self.inner.close().await
+    }
+
+    async fn exec(&self, sql: &str) -> databend_driver::Result<i64> {
+        let start_us = now_us();
+        let result = self.inner.exec(sql).await;
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.record(sql, start_us, error);
+        result
+    }
+
+    /// The rows are fetched before the statement is recorded as done
+    async fn query_iter(&self, sql: &str) -> databend_driver::Result<RowIterator> {
+        let start_us = now_us();
+        let rows = match self.inner.query_iter(sql).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.record(sql, start_us, Some(e.to_string()));
+                return Err(e);
+            }
+        };
+        let schema = rows.schema();
+        let rows: Vec<_> = rows.collect().await;
+        let error = rows
+            .iter()
+            .find_map(|row| row.as_ref().err().map(|e| e.to_string()));
+        self.record(sql, start_us, error);
+        Ok(RowIterator::new(schema, Box::pin(stream::iter(rows))))
+    }
+
+    /// Like [`RecordingConnection::query_iter`], the rows and stats are fetched first
+    async fn query_iter_ext(&self, sql: &str) -> databend_driver::Result<RowStatsIterator> {
+        let start_us = now_us();
+        let rows = match self.inner.query_iter_ext(sql).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.record(sql, start_us, Some(e.to_string()));
+                return Err(e);
+            }
+        };
+        let schema = rows.schema();
+        let rows: Vec<_> = rows.collect().await;
+        let error = rows
+            .iter()
+            .find_map(|row| row.as_ref().err().map(|e| e.to_string()));
+        self.record(sql, start_us, error);
+        Ok(RowStatsIterator::new(schema, Box::pin(stream::iter(rows))))
+    }
+
+    async fn get_presigned_url(
+        &self,
+        operation: &str,
+        stage: &str,
+    ) -> databend_driver::Result<PresignedResponse> {
+        self.inner.get_presigned_url(operation, stage).await
+    }
+
+    async fn upload_to_stage(
+        &self,
+        stage: &str,
+        data: Reader,
+        size: u64,
+    ) -> databend_driver::Result<()> {
+        self.inner.upload_to_stage(stage, data, size).await
+    }
+
+    async fn load_data(
+        &self,
+        sql: &str,
+        data: Reader,
+        size: u64,
+        file_format_options: Option<BTreeMap<&str, &str>>,
+        copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner
+            .load_data(sql, data, size, file_format_options, copy_options)
+            .await
+    }
+
+    async fn load_file(
+        &self,
+        sql: &str,
+        fp: &Path,
+        format_options: BTreeMap<&str, &str>,
+        copy_options: Option<BTreeMap<&str, &str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner
+            .load_file(sql, fp, format_options, copy_options)
+            .await
+    }
+
+    async fn stream_load(
+        &self,
+        sql: &str,
+        data: Vec<Vec<&str>>,
+    ) -> databend_driver::Result<ServerStats> {
+        self.inner.stream_load(sql, data).await
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use log::{info, warn};
+use tokio::time::Instant;
+
+use crate::record::TraceRecord;
+use crate::suite::SuiteContext;
+
+/// Re-issue a trace recorded by `--record`, one connection per recorded session
+#[derive(Parser, Clone, Debug)]
+pub struct Args {
+    /// Trace file written by `--record`
+    #[arg(long)]
+    trace: PathBuf,
+
+    /// Issue the statements of each session back to back, instead of at their recorded offsets
+    #[arg(long, default_value_t = false)]
+    no_timing: bool,
+}
+
+/// Replays the sessions of the trace concurrently, each of them in its recorded order.
+/// Unless `--no-timing`, a statement is not issued before its recorded offset from the
+/// start of the trace, which keeps the interleaving of the sessions close to the recorded one.
+pub async fn run(args: Args, ctx: SuiteContext) -> Result<()> {
+    let mut records = TraceRecord::load_all(&args.trace)?;
+    // the trace is written in the order the statements end
+    records.sort_by_key(|r| r.start_us);
+    let Some(trace_start_us) = records.first().map(|r| r.start_us) else {
+        info!("trace {} is empty", args.trace.display());
+        return Ok(());
+    };
+
+    let mut sessions: BTreeMap<u64, Vec<TraceRecord>> = BTreeMap::new();
+    for record in records {
+        sessions.entry(record.session).or_default().push(record);
+    }
+    info!(
+        "###replay###: {} statements of {} sessions",
+        sessions.values().map(Vec::len).sum::<usize>(),
+        sessions.len()
+    );
+
+    let replay_start = Instant::now();
+    let mut handles = Vec::with_capacity(sessions.len());
+    for (session, records) in sessions {
+        let ctx = ctx.clone();
+        let no_timing = args.no_timing;
+        handles.push(tokio::spawn(async move {
+            let conn = ctx.new_connection().await?;
+            let mut diverged = 0;
+            for record in &records {
+                if !no_timing {
+                    let offset = Duration::from_micros(record.start_us - trace_start_us);
+                    tokio::time::sleep_until(replay_start + offset).await;
+                }
+                let result = ctx.stats().timed("replay", conn.exec(&record.sql)).await;
+                if result.is_err() != record.error.is_some() {
+                    diverged += 1;
+                    warn!(
+                        "session {session}: `{}` recorded {}, replayed {}",
+                        record.sql,
+                        record.error.as_deref().unwrap_or("ok"),
+                        result
+                            .err()
+                            .map_or_else(|| "ok".to_owned(), |e| e.to_string())
+                    );
+                }
+            }
+            Ok::<_, anyhow::Error>(diverged)
+        }));
+    }
+
+    let mut diverged = 0;
+    for handle in handles {
+        diverged += handle.await??;
+    }
+    info!("###replay###: {diverged} statements ended differently than recorded");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock::{MockServer, Reply};
+    use crate::record::Recorder;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replays_the_recorded_sessions() {
+        let trace = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+        let recorded = MockServer::new();
+        recorded.when("insert into t values(2)", Reply::error(4001, "conflict"));
+        let ctx = recorded
+            .context()
+            .with_recorder(Arc::new(Recorder::create(&trace).unwrap()))
+            .with_query_tracking(true);
+        let c1 = ctx.new_connection().await.unwrap();
+        let c2 = ctx.new_connection().await.unwrap();
+        c1.exec("begin").await.unwrap();
+        c1.exec("insert into t values(1)").await.unwrap();
+        c2.exec("insert into t values(2)").await.unwrap_err();
+        c1.exec("commit").await.unwrap();
+
+        let records = TraceRecord::load_all(&trace).unwrap();
+        let statements: Vec<_> = records
+            .iter()
+            .map(|r| (r.session, r.sql.as_str()))
+            .collect();
+        assert_eq!(
+            statements,
+            [
+                (0, "begin"),
+                (0, "insert into t values(1)"),
+                (1, "insert into t values(2)"),
+                (0, "commit")
+            ]
+        );
+        assert!(records[2].error.as_ref().unwrap().contains("conflict"));
+        assert!(records.iter().all(|r| r.start_us <= r.end_us));
+        // the ids of the tags, no statement is issued to ask for them
+        let query_ids: Vec<_> = records.iter().map(|r| r.query_id.clone()).collect();
+        let tags: Vec<_> = (0..4)
+            .map(|seq| Some(format!("ts_{}_q{seq}", ctx.run_id())))
+            .collect();
+        assert_eq!(query_ids, tags);
+        assert_eq!(recorded.statements().len(), 4);
+
+        let replayed = MockServer::new();
+        let ctx = replayed.context();
+        let args = Args {
+            trace: trace.clone(),
+            no_timing: false,
+        };
+        run(args, ctx.clone()).await.unwrap();
+        std::fs::remove_file(&trace).unwrap();
+
+        assert_eq!(replayed.statements().len(), 4);
+        assert_eq!(replayed.connections(), 2);
+        let c1_statements: Vec<_> = replayed
+            .statements()
+            .into_iter()
+            .filter(|sql| !sql.contains("values(2)"))
+            .collect();
+        assert_eq!(
+            c1_statements,
+            ["begin", "insert into t values(1)", "commit"]
+        );
+        assert_eq!(ctx.stats().snapshot()["replay"].success, 4);
+    }
+}
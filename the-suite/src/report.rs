@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::errors::ErrorCategory;
+use crate::reconcile::Reconciliation;
+use crate::stats::{LatencySummary, OpCounts};
+use crate::suite::{Skipped, SuiteContext};
+
+/// Outcome of a suite run, i.e. a test case of the reports
+#[derive(Serialize, Debug)]
+pub struct CaseReport {
+    /// e.g. `change-tracking --append-only-stream`
+    pub name: String,
+    pub suite: String,
+    /// Id of the run, which names its databases
+    pub run_id: String,
+    pub duration_secs: f64,
+    pub passed: bool,
+    pub failure: Option<String>,
+    /// Why the suite was not run, e.g. a feature the server lacks, neither passed nor failed
+    pub skipped: Option<String>,
+    /// Stopped early by Ctrl-C or SIGTERM, the verification ran on a partial workload
+    pub interrupted: bool,
+    pub server_version: Option<String>,
+    /// Seed of the generated data, to run the case again with the same data
+    pub seed: u64,
+    pub ops: BTreeMap<String, OpCounts>,
+    /// Number of the failed operations by category of error
+    pub errors: BTreeMap<ErrorCategory, u64>,
+    /// Latency percentiles and throughput of the operations by kind
+    pub latencies: BTreeMap<String, LatencySummary>,
+    /// Outcomes of the statements on the client against those in the query log of the server
+    pub reconciliation: Option<Reconciliation>,
+}
+
+/// Runs a suite (the future returned by `SuiteEntry::start`) and collects its report
+pub async fn run_case(
+    name: String,
+    suite: &str,
+    fut: BoxFuture<'static, Result<()>>,
+    ctx: &SuiteContext,
+) -> CaseReport {
+    let server_version = server_version(ctx).await;
+    let start = Instant::now();
+
+    // suites may panic on failed assertions, run each in its own task so that
+    // the panic is reported as a failure of the case
+    let result = match tokio::spawn(fut).await {
+        Ok(result) => result,
+        Err(e) => Err(anyhow!("suite panicked: {e}")),
+    };
+
+    let skipped = match &result {
+        Err(e) => e.downcast_ref::<Skipped>().map(|skipped| skipped.0.clone()),
+        Ok(_) => None,
+    };
+
+    let elapsed = start.elapsed();
+    let latencies = ctx.stats().latencies(elapsed);
+    print_latencies(&name, &latencies);
+    let reconciliation = reconcile(&name, ctx).await;
+    CaseReport {
+        name,
+        suite: suite.to_owned(),
+        run_id: ctx.run_id().to_owned(),
+        duration_secs: elapsed.as_secs_f64(),
+        passed: result.is_ok(),
+        failure: result
+            .err()
+            .filter(|_| skipped.is_none())
+            .map(|e| format!("{e:#}")),
+        skipped,
+        interrupted: ctx.shutdown().is_requested(),
+        server_version,
+        seed: ctx.seed(),
+        ops: ctx.stats().snapshot(),
+        errors: ctx.stats().errors(),
+        latencies,
+        reconciliation,
+    }
+}
+
+async fn reconcile(name: &str, ctx: &SuiteContext) -> Option<Reconciliation> {
+    let tracker = ctx.query_tracker()?;
+    let result = async { tracker.reconcile(&ctx.node_connections().await?).await }.await;
+    match result {
+        Ok(reconciliation) => {
+            reconciliation.log(name);
+            Some(reconciliation)
+        }
+        Err(e) => {
+            warn!("[{name}] failed to reconcile the statements with the query log: {e:#}");
+            None
+        }
+    }
+}
+
+fn print_latencies(name: &str, latencies: &BTreeMap<String, LatencySummary>) {
+    if latencies.is_empty() {
+        return;
+    }
+    info!("===== [{name}] latencies =====");
+    for line in latency_table(latencies) {
+        info!("{line}");
+    }
+}
+
+/// A header, then a line per kind of operation
+fn latency_table(latencies: &BTreeMap<String, LatencySummary>) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<20} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "op", "count", "p50(ms)", "p90(ms)", "p99(ms)", "max(ms)", "ops/sec"
+    )];
+    let ms = |us: u64| us as f64 / 1000.0;
+    for (kind, l) in latencies {
+        lines.push(format!(
+            "{:<20} {:>10} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+            kind,
+            l.count,
+            ms(l.p50_us),
+            ms(l.p90_us),
+            ms(l.p99_us),
+            ms(l.max_us),
+            l.ops_per_sec
+        ));
+    }
+    lines
+}
+
+async fn server_version(ctx: &SuiteContext) -> Option<String> {
+    let conn = ctx.new_connection().await.ok()?;
+    conn.version().await.ok()
+}
+
+pub fn write_json(path: &Path, cases: &[CaseReport]) -> Result<()> {
+    let json = serde_json::to_string_pretty(cases)?;
+    std::fs::write(path, json)?;
+    info!("json report written to {}", path.display());
+    Ok(())
+}
+
+pub fn write_junit(path: &Path, cases: &[CaseReport]) -> Result<()> {
+    std::fs::write(path, junit(cases)?)?;
+    info!("junit report written to {}", path.display());
+    Ok(())
+}
+
+fn junit(cases: &[CaseReport]) -> Result<String> {
+    let failures = cases.iter().filter(|c| c.failure.is_some()).count();
+    let skipped = cases.iter().filter(|c| c.skipped.is_some()).count();
+    let time: f64 = cases.iter().map(|c| c.duration_secs).sum();
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<testsuites name="test-scripts" tests="{}" failures="{failures}" time="{time:.3}">"#,
+        cases.len()
+    )?;
+    writeln!(
+        xml,
+        r#"  <testsuite name="test-scripts" tests="{}" failures="{failures}" skipped="{skipped}" time="{time:.3}">"#,
+        cases.len()
+    )?;
+    for case in cases {
+        writeln!(
+            xml,
+            r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+            escape(&case.name),
+            escape(&case.suite),
+            case.duration_secs
+        )?;
+        write_properties(&mut xml, case)?;
+        if let Some(failure) = &case.failure {
+            let message = failure.lines().next().unwrap_or_default();
+            writeln!(
+                xml,
+                r#"      <failure message="{}">{}</failure>"#,
+                escape(message),
+                escape(failure)
+            )?;
+        }
+        if let Some(reason) = &case.skipped {
+            writeln!(xml, r#"      <skipped message="{}"/>"#, escape(reason))?;
+        }
+        writeln!(xml, "    </testcase>")?;
+    }
+    writeln!(xml, "  </testsuite>")?;
+    writeln!(xml, "</testsuites>")?;
+    Ok(xml)
+}
+
+fn write_properties(xml: &mut String, case: &CaseReport) -> Result<()> {
+    writeln!(xml, "      <properties>")?;
+    writeln!(
+        xml,
+        r#"        <property name="seed" value="{}"/>"#,
+        case.seed
+    )?;
+    writeln!(
+        xml,
+        r#"        <property name="run_id" value="{}"/>"#,
+        escape(&case.run_id)
+    )?;
+    if case.interrupted {
+        writeln!(
+            xml,
+            r#"        <property name="interrupted" value="true"/>"#
+        )?;
+    }
+    if let Some(version) = &case.server_version {
+        writeln!(
+            xml,
+            r#"        <property name="server_version" value="{}"/>"#,
+            escape(version)
+        )?;
+    }
+    for (kind, counts) in &case.ops {
+        writeln!(
+            xml,
+            r#"        <property name="ops.{}.success" value="{}"/>"#,
+            escape(kind),
+            counts.success
+        )?;
+        writeln!(
+            xml,
+            r#"        <property name="ops.{}.failed" value="{}"/>"#,
+            escape(kind),
+            counts.failed
+        )?;
+        writeln!(
+            xml,
+            r#"        <property name="ops.{}.attempts" value="{}"/>"#,
+            escape(kind),
+            counts.attempts
+        )?;
+    }
+    for (kind, l) in &case.latencies {
+        let values = [
+            ("p50_us", l.p50_us.to_string()),
+            ("p90_us", l.p90_us.to_string()),
+            ("p99_us", l.p99_us.to_string()),
+            ("max_us", l.max_us.to_string()),
+            ("ops_per_sec", format!("{:.3}", l.ops_per_sec)),
+        ];
+        for (name, value) in values {
+            writeln!(
+                xml,
+                r#"        <property name="latency.{}.{name}" value="{value}"/>"#,
+                escape(kind)
+            )?;
+        }
+    }
+    if let Some(r) = &case.reconciliation {
+        let values = [
+            ("statements", r.statements),
+            ("unlogged", r.unlogged),
+            (
+                "client_success_server_failure",
+                r.client_success_server_failure.len() as u64,
+            ),
+            ("ambiguous_commits", r.ambiguous_commits.len() as u64),
+        ];
+        for (name, value) in values {
+            writeln!(
+                xml,
+                r#"        <property name="reconcile.{name}" value="{value}"/>"#
+            )?;
+        }
+    }
+    for (category, count) in &case.errors {
+        writeln!(
+            xml,
+            r#"        <property name="errors.{category}" value="{count}"/>"#
+        )?;
+    }
+    writeln!(xml, "      </properties>")?;
+    Ok(())
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str, failure: Option<&str>, skipped: Option<&str>) -> CaseReport {
+        CaseReport {
+            name: name.to_owned(),
+            suite: name.split(' ').next().unwrap().to_owned(),
+            run_id: "20250101_120000_3fa2c1".to_owned(),
+            duration_secs: 1.5,
+            passed: failure.is_none() && skipped.is_none(),
+            failure: failure.map(str::to_owned),
+            skipped: skipped.map(str::to_owned),
+            interrupted: false,
+            server_version: Some("v1.2.3".to_owned()),
+            seed: 42,
+            ops: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            latencies: BTreeMap::new(),
+            reconciliation: None,
+        }
+    }
+
+    fn commit_latencies() -> LatencySummary {
+        LatencySummary {
+            count: 10,
+            p50_us: 1500,
+            p90_us: 2500,
+            p99_us: 9000,
+            max_us: 12345,
+            ops_per_sec: 2.5,
+        }
+    }
+
+    fn cases() -> Vec<CaseReport> {
+        let mut passed = case("vacuum2 --explicit-txn", None, None);
+        let counts = OpCounts {
+            success: 9,
+            failed: 1,
+            attempts: 10,
+        };
+        passed.ops.insert("insert".to_owned(), counts);
+        passed.errors.insert(ErrorCategory::Conflict, 1);
+        passed
+            .latencies
+            .insert("commit".to_owned(), commit_latencies());
+        vec![
+            passed,
+            case(
+                "change-tracking",
+                Some("sinks diverged: <sink_1> & \"sink_2\"\nat 'verify'"),
+                None,
+            ),
+            case(
+                "auto-vacuum",
+                None,
+                Some("setting enable_auto_vacuum is unknown"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn junit_report() {
+        let xml = junit(&cases()).unwrap();
+        assert!(xml.contains(
+            r#"<testsuite name="test-scripts" tests="3" failures="1" skipped="1" time="4.500">"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase name="vacuum2 --explicit-txn" classname="vacuum2" time="1.500">"#
+        ));
+        assert!(xml.contains(r#"<property name="seed" value="42"/>"#));
+        assert!(xml.contains(r#"<property name="server_version" value="v1.2.3"/>"#));
+        assert!(xml.contains(r#"<property name="ops.insert.failed" value="1"/>"#));
+        assert!(xml.contains(r#"<property name="errors.conflict" value="1"/>"#));
+        assert!(xml.contains(r#"<property name="latency.commit.p99_us" value="9000"/>"#));
+        assert!(xml.contains(r#"<property name="latency.commit.ops_per_sec" value="2.500"/>"#));
+        // the message is the first line of the failure, both escaped
+        let escaped = "sinks diverged: &lt;sink_1&gt; &amp; &quot;sink_2&quot;";
+        assert!(xml.contains(&format!(
+            "<failure message=\"{escaped}\">{escaped}\nat &apos;verify&apos;</failure>"
+        )));
+        assert!(xml.contains(r#"<skipped message="setting enable_auto_vacuum is unknown"/>"#));
+        assert_eq!(xml.matches("<testcase ").count(), 3);
+        assert_eq!(xml.matches("</testcase>").count(), 3);
+    }
+
+    #[test]
+    fn json_report() {
+        let cases = cases();
+        let json = serde_json::to_string_pretty(&cases).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let parsed = parsed.as_array().unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0]["name"], "vacuum2 --explicit-txn");
+        assert_eq!(parsed[0]["passed"], true);
+        assert_eq!(parsed[0]["seed"], 42);
+        assert_eq!(parsed[0]["ops"]["insert"]["attempts"], 10);
+        assert_eq!(parsed[0]["errors"]["conflict"], 1);
+        assert_eq!(parsed[0]["latencies"]["commit"]["max_us"], 12345);
+        assert_eq!(parsed[0]["latencies"]["commit"]["ops_per_sec"], 2.5);
+        assert_eq!(parsed[1]["passed"], false);
+        assert_eq!(parsed[1]["failure"], cases[1].failure.as_deref().unwrap());
+        assert_eq!(parsed[1]["skipped"], serde_json::Value::Null);
+        assert_eq!(parsed[2]["passed"], false);
+        assert_eq!(parsed[2]["failure"], serde_json::Value::Null);
+        assert_eq!(
+            parsed[2]["skipped"],
+            "setting enable_auto_vacuum is unknown"
+        );
+    }
+
+    #[test]
+    fn latency_table() {
+        let latencies = BTreeMap::from([("commit".to_owned(), commit_latencies())]);
+        assert_eq!(
+            super::latency_table(&latencies),
+            [
+                "op                        count    p50(ms)    p90(ms)    p99(ms)    max(ms)    ops/sec",
+                "commit                       10        1.5        2.5        9.0       12.3        2.5",
+            ]
+        );
+    }
+}
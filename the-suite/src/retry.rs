@@ -0,0 +1,244 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+use log::info;
+use rand::Rng;
+
+use crate::errors::{ErrorCategory, OpError};
+use crate::stats::Stats;
+
+/// Retries of the workload operations, shared by all the suites
+#[derive(Args, Clone, Debug)]
+pub struct RetryArgs {
+    /// Attempts of a workload operation (a statement, or a whole explicit transaction), 1 never retries
+    #[arg(long, global = true, default_value_t = 1)]
+    retry_attempts: u32,
+
+    /// Backoff before the first retry, doubled on each of the next ones
+    #[arg(long, global = true, default_value_t = 50)]
+    retry_backoff_ms: u64,
+
+    /// Upper bound of the backoff
+    #[arg(long, global = true, default_value_t = 2000)]
+    retry_max_backoff_ms: u64,
+
+    /// Categories of the errors worth retrying, only the transient ones: `conflict`, `retryable`,
+    /// `timeout` and `connection`
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        value_parser = parse_retry_on,
+        default_values_t = [ErrorCategory::Conflict, ErrorCategory::Retryable]
+    )]
+    retry_on: Vec<ErrorCategory>,
+}
+
+/// A category of `--retry-on`, a retried corruption or unknown error would go unnoticed
+fn parse_retry_on(s: &str) -> Result<ErrorCategory, String> {
+    match ErrorCategory::from_str(s, true)? {
+        category @ (ErrorCategory::Corruption | ErrorCategory::Unknown) => Err(format!(
+            "{category} errors are not transient, retrying them would hide them"
+        )),
+        category => Ok(category),
+    }
+}
+
+/// How a failed workload operation is retried: up to `max_attempts` attempts, as long as its
+/// errors are of the `retry_on` categories, sleeping an exponential backoff with full jitter in between
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub retry_on: Vec<ErrorCategory>,
+}
+
+impl Default for RetryPolicy {
+    /// Never retries
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(2000),
+            retry_on: vec![ErrorCategory::Conflict, ErrorCategory::Retryable],
+        }
+    }
+}
+
+impl From<&RetryArgs> for RetryPolicy {
+    fn from(args: &RetryArgs) -> Self {
+        Self {
+            max_attempts: args.retry_attempts.max(1),
+            backoff: Duration::from_millis(args.retry_backoff_ms),
+            max_backoff: Duration::from_millis(args.retry_max_backoff_ms),
+            retry_on: args.retry_on.clone(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `op` until it succeeds, or fails with an error not worth retrying, or runs out of
+    /// attempts. Every attempt is counted in `stats` as an operation of `kind`.
+    pub async fn run<T, E, F, Fut>(&self, stats: &Stats, kind: &str, op: F) -> Result<T, E>
+    where
+        E: OpError,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_from(stats, kind, Instant::now(), op).await
+    }
+
+    /// Like [`RetryPolicy::run`], the latency of the first attempt is measured from `scheduled`,
+    /// the start of the operation in the schedule of an open loop
+    pub async fn run_from<T, E, F, Fut>(
+        &self,
+        stats: &Stats,
+        kind: &str,
+        scheduled: Instant,
+        mut op: F,
+    ) -> Result<T, E>
+    where
+        E: OpError,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        let mut start = scheduled;
+        loop {
+            let result = op().await;
+            stats.record_latency(kind, start.elapsed());
+            match &result {
+                Err(e) if attempt < self.max_attempts && self.retry_on.contains(&e.category()) => {
+                    stats.record_retried(kind, e);
+                    let backoff = self.backoff(attempt);
+                    info!(
+                        "{kind} failed, retrying in {backoff:?} ({attempt}/{}): {e}",
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                    start = Instant::now();
+                }
+                _ => {
+                    stats.record(kind, &result);
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Uniformly random up to `backoff * 2^(attempt - 1)`, capped by `max_backoff`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .backoff
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_backoff);
+        exp.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    fn error(message: &str) -> anyhow::Error {
+        anyhow::anyhow!(databend_driver::Error::Transport(message.to_owned()))
+    }
+
+    #[tokio::test]
+    async fn retries_the_categories_until_the_attempts_run_out() {
+        let stats = Stats::default();
+        let mut calls = 0;
+        let result: anyhow::Result<()> = policy(3)
+            .run(&stats, "insert", || {
+                calls += 1;
+                async { Err(anyhow::anyhow!("table version mismatched")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1, "an unknown error is not retried");
+
+        let policy = RetryPolicy {
+            retry_on: vec![ErrorCategory::Connection],
+            ..policy(3)
+        };
+        let mut calls = 0;
+        let result = policy
+            .run(&stats, "merge", || {
+                calls += 1;
+                let result = if calls < 3 {
+                    Err(error("reset"))
+                } else {
+                    Ok(calls)
+                };
+                async move { result }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let ops = stats.snapshot();
+        assert_eq!(
+            (
+                ops["merge"].attempts,
+                ops["merge"].success,
+                ops["merge"].failed
+            ),
+            (3, 1, 0)
+        );
+        assert_eq!((ops["insert"].attempts, ops["insert"].failed), (1, 1));
+        assert_eq!(stats.errors()[&ErrorCategory::Connection], 2);
+    }
+
+    #[test]
+    fn retries_only_the_transient_categories() {
+        #[derive(Parser)]
+        struct Cli {
+            #[command(flatten)]
+            retry: RetryArgs,
+        }
+        let retry_on = |arg: &str| {
+            Cli::try_parse_from(["suite", "--retry-on", arg]).map(|cli| cli.retry.retry_on)
+        };
+        assert_eq!(
+            retry_on("conflict,timeout,connection").unwrap(),
+            [
+                ErrorCategory::Conflict,
+                ErrorCategory::Timeout,
+                ErrorCategory::Connection
+            ]
+        );
+        let error = retry_on("conflict,corruption").unwrap_err().to_string();
+        assert!(
+            error.contains("corruption errors are not transient"),
+            "{error}"
+        );
+        assert!(retry_on("unknown").is_err());
+        assert!(retry_on("flaky").is_err());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 100,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(100),
+            ..Default::default()
+        };
+        for attempt in 1..100 {
+            let cap = Duration::from_millis(10 << (attempt - 1).min(4)).min(policy.max_backoff);
+            assert!(policy.backoff(attempt) <= cap);
+        }
+    }
+}
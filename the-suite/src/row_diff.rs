@@ -0,0 +1,276 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, Result};
+use databend_driver::Connection;
+use log::info;
+
+use crate::util::ConnectionExt;
+
+/// Differing rows and duplicate keys listed by a [`TableDiff`] at most, the others are only counted
+pub const LISTED_ROWS: usize = 20;
+
+/// How the rows of a table differ from those of the table expected, matched by a key column and
+/// compared by a hash of the full row
+#[derive(Debug, Default, PartialEq)]
+pub struct TableDiff {
+    pub table: String,
+    /// Rows of the expected table whose keys are not in the table
+    pub missing: u64,
+    /// Rows of the table whose keys are not in the expected table
+    pub extra: u64,
+    /// Rows whose keys are in both tables, with other values
+    pub mismatched: u64,
+    /// Keys of several rows of the table, with their numbers of rows
+    pub duplicates: Vec<(String, u64)>,
+    /// The differing rows, ordered by key
+    pub rows: Vec<RowDiff>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffKind {
+    Missing,
+    Extra,
+    Mismatched,
+}
+
+impl DiffKind {
+    fn parse(kind: &str) -> Result<Self> {
+        match kind {
+            "missing" => Ok(DiffKind::Missing),
+            "extra" => Ok(DiffKind::Extra),
+            "mismatched" => Ok(DiffKind::Mismatched),
+            kind => Err(anyhow!("unknown kind of row diff `{kind}`")),
+        }
+    }
+}
+
+impl Display for DiffKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffKind::Missing => write!(f, "missing"),
+            DiffKind::Extra => write!(f, "extra"),
+            DiffKind::Mismatched => write!(f, "mismatched"),
+        }
+    }
+}
+
+/// A row differing between the tables, with its values in each of them, e.g. `1, 2, NULL`
+#[derive(Debug, PartialEq)]
+pub struct RowDiff {
+    pub kind: DiffKind,
+    pub key: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing == 0 && self.extra == 0 && self.mismatched == 0 && self.duplicates.is_empty()
+    }
+
+    /// e.g. `sink_0: 1 missing, 0 extra, 2 mismatched rows, 1 duplicate keys`
+    pub fn summary(&self) -> String {
+        format!(
+            "{}: {} missing, {} extra, {} mismatched rows, {} duplicate keys",
+            self.table,
+            self.missing,
+            self.extra,
+            self.mismatched,
+            self.duplicates.len()
+        )
+    }
+
+    /// Logs the summary, then the differing rows and duplicate keys listed
+    pub fn log(&self) {
+        info!("{}", self.summary());
+        for row in &self.rows {
+            let values = |values: &Option<String>| values.clone().unwrap_or("-".to_owned());
+            info!(
+                "  {} row {}: expected ({}), actual ({})",
+                row.kind,
+                row.key,
+                values(&row.expected),
+                values(&row.actual)
+            );
+        }
+        for (key, rows) in &self.duplicates {
+            info!("  duplicate key {key}: {rows} rows");
+        }
+    }
+}
+
+/// Keys of several rows of `table`, with their numbers of rows, [`LISTED_ROWS`] at most
+pub async fn duplicates(
+    conn: &dyn Connection,
+    table: &str,
+    key: &str,
+) -> Result<Vec<(String, u64)>> {
+    let sql = format!(
+        "SELECT {key}, count() AS n FROM {table} GROUP BY {key} HAVING n > 1 \
+         ORDER BY {key} LIMIT {LISTED_ROWS}"
+    );
+    conn.query_strings(&sql)
+        .await?
+        .into_iter()
+        .map(|row| match row.as_slice() {
+            [key, rows] => Ok((key.clone(), rows.parse()?)),
+            _ => Err(anyhow!("unexpected row {row:?} of duplicate keys")),
+        })
+        .collect()
+}
+
+/// Compares the rows of `actual` with those of `expected`, two tables of the `columns`, e.g.
+/// `a, b, c`, matched by the `key` column, and looks for the duplicate keys of `actual`
+pub async fn diff(
+    conn: &dyn Connection,
+    expected: &str,
+    actual: &str,
+    key: &str,
+    columns: &str,
+) -> Result<TableDiff> {
+    let columns: Vec<_> = columns.split(',').map(str::trim).collect();
+    let row_text = columns
+        .iter()
+        .map(|column| format!("coalesce({column}::STRING, 'NULL')"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // rows are compared by a hash of the hashes of their values, which no separator within a value
+    // can shift, and in which a NULL (no number) only matches a NULL, the text is only displayed
+    let row_hash = columns
+        .iter()
+        .map(|column| format!("coalesce(siphash64({column})::STRING, 'NULL')"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // NULL keys match too, told apart from the empty string by `k_null`, and a row is on a side if
+    // its `present` marker is set, whatever its key
+    let side = |table: &str| {
+        format!(
+            "SELECT {key} AS k, {key} IS NULL AS k_null, coalesce({key}::STRING, '') AS k_join, \
+             TRUE AS present, concat_ws(', ', {row_text}) AS row_text, \
+             siphash64(concat_ws(',', {row_hash})) AS row_hash FROM {table}"
+        )
+    };
+    let diff = format!(
+        "SELECT CASE WHEN a.present IS NULL THEN 'missing' WHEN e.present IS NULL THEN 'extra' \
+         ELSE 'mismatched' END AS kind, coalesce(e.k, a.k)::STRING AS k, e.row_text, a.row_text \
+         FROM ({}) AS e FULL OUTER JOIN ({}) AS a ON e.k_join = a.k_join AND e.k_null = a.k_null \
+         WHERE e.present IS NULL OR a.present IS NULL OR e.row_hash <> a.row_hash",
+        side(expected),
+        side(actual)
+    );
+
+    let mut table_diff = TableDiff {
+        table: actual.to_owned(),
+        duplicates: duplicates(conn, actual, key).await?,
+        ..Default::default()
+    };
+    let counts = format!("SELECT kind, count() FROM ({diff}) GROUP BY kind");
+    for row in conn.query_strings(&counts).await? {
+        let [kind, rows] = row.as_slice() else {
+            return Err(anyhow!("unexpected row {row:?} of row diff counts"));
+        };
+        let rows = rows.parse()?;
+        match DiffKind::parse(kind)? {
+            DiffKind::Missing => table_diff.missing = rows,
+            DiffKind::Extra => table_diff.extra = rows,
+            DiffKind::Mismatched => table_diff.mismatched = rows,
+        }
+    }
+    if table_diff.missing + table_diff.extra + table_diff.mismatched == 0 {
+        return Ok(table_diff);
+    }
+    let listed = format!("{diff} ORDER BY k LIMIT {LISTED_ROWS}");
+    for row in conn.query_strings(&listed).await? {
+        let [kind, key, expected, actual] = row.as_slice() else {
+            return Err(anyhow!("unexpected row {row:?} of row diff"));
+        };
+        let kind = DiffKind::parse(kind)?;
+        table_diff.rows.push(RowDiff {
+            kind,
+            key: key.clone(),
+            expected: (kind != DiffKind::Extra).then(|| expected.clone()),
+            actual: (kind != DiffKind::Missing).then(|| actual.clone()),
+        });
+    }
+    Ok(table_diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, uint, MockServer, Reply};
+
+    #[tokio::test]
+    async fn lists_the_differing_rows() {
+        let mock = MockServer::new();
+        mock.when(
+            "HAVING n > 1",
+            Reply::rows(vec![vec![string("uuid-3"), uint(2)]]),
+        );
+        mock.when(
+            "GROUP BY kind",
+            Reply::rows(vec![
+                vec![string("missing"), uint(1)],
+                vec![string("mismatched"), uint(1)],
+            ]),
+        );
+        mock.when(
+            "ORDER BY k",
+            Reply::rows(vec![
+                vec![
+                    string("missing"),
+                    string("uuid-1"),
+                    string("1, 2, uuid-1"),
+                    string("NULL"),
+                ],
+                vec![
+                    string("mismatched"),
+                    string("uuid-2"),
+                    string("1, 2, uuid-2"),
+                    string("1, 3, uuid-2"),
+                ],
+            ]),
+        );
+        let conn = mock.context().new_connection().await.unwrap();
+
+        let diff = diff(&*conn, "sink", "sink_1", "c", "a, b, c")
+            .await
+            .unwrap();
+        assert_eq!(
+            diff,
+            TableDiff {
+                table: "sink_1".to_owned(),
+                missing: 1,
+                extra: 0,
+                mismatched: 1,
+                duplicates: vec![("uuid-3".to_owned(), 2)],
+                rows: vec![
+                    RowDiff {
+                        kind: DiffKind::Missing,
+                        key: "uuid-1".to_owned(),
+                        expected: Some("1, 2, uuid-1".to_owned()),
+                        actual: None,
+                    },
+                    RowDiff {
+                        kind: DiffKind::Mismatched,
+                        key: "uuid-2".to_owned(),
+                        expected: Some("1, 2, uuid-2".to_owned()),
+                        actual: Some("1, 3, uuid-2".to_owned()),
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            diff.summary(),
+            "sink_1: 1 missing, 0 extra, 1 mismatched rows, 1 duplicate keys"
+        );
+        let statements = mock.statements();
+        assert!(statements.iter().any(|sql| sql.contains("FULL OUTER JOIN")));
+        assert!(statements
+            .iter()
+            .any(|sql| sql.contains("coalesce(siphash64(b)::STRING, 'NULL')")));
+        assert!(statements
+            .iter()
+            .any(|sql| sql.contains("CASE WHEN a.present IS NULL THEN 'missing'")));
+    }
+}
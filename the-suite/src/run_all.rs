@@ -0,0 +1,310 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::report::{run_case, CaseReport};
+use crate::suite::{SuiteContext, SuiteEntry};
+
+/// Run every entry of a test matrix, in-process, one after another
+#[derive(Parser, Clone, Debug)]
+pub struct Args {
+    /// Path of the matrix file
+    #[arg(long, default_value = "./matrix.toml")]
+    matrix: PathBuf,
+
+    /// Only run the entries matching one of these suite names, tags or entry names
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// Skip the entries matching one of these suite names, tags or entry names
+    #[arg(long)]
+    exclude: Vec<String>,
+}
+
+/// The matrix file, e.g.
+///
+/// ```toml
+/// [[suite]]
+/// name = "change-tracking"
+/// tags = ["stream"]
+/// args = [[], ["--append-only-stream"]]
+/// ```
+///
+/// expands to one entry per argument combination of each suite
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Matrix {
+    #[serde(default)]
+    suite: Vec<MatrixSuite>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct MatrixSuite {
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Argument combinations, the suite runs once with its defaults if empty
+    #[serde(default)]
+    args: Vec<Vec<String>>,
+}
+
+#[derive(Debug)]
+struct MatrixEntry {
+    suite: String,
+    tags: Vec<String>,
+    args: Vec<String>,
+}
+
+impl MatrixEntry {
+    /// e.g. `change-tracking --append-only-stream`
+    fn name(&self) -> String {
+        std::iter::once(self.suite.as_str())
+            .chain(self.args.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn matches(&self, filter: &str) -> bool {
+        self.suite == filter || self.tags.iter().any(|t| t == filter) || self.name() == filter
+    }
+}
+
+impl Matrix {
+    fn load(path: &PathBuf) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read matrix file {}: {e}", path.display()))?;
+        let matrix = toml::from_str(&content)
+            .map_err(|e| anyhow!("invalid matrix file {}: {e}", path.display()))?;
+        Ok(matrix)
+    }
+
+    fn entries(self) -> Vec<MatrixEntry> {
+        let mut entries = Vec::new();
+        for suite in self.suite {
+            let combinations = if suite.args.is_empty() {
+                vec![vec![]]
+            } else {
+                suite.args
+            };
+            for args in combinations {
+                entries.push(MatrixEntry {
+                    suite: suite.name.clone(),
+                    tags: suite.tags.clone(),
+                    args,
+                });
+            }
+        }
+        entries
+    }
+}
+
+/// The entries matching one of `include`, all of them if empty, and none of `exclude`
+fn select(entries: Vec<MatrixEntry>, include: &[String], exclude: &[String]) -> Vec<MatrixEntry> {
+    entries
+        .into_iter()
+        .filter(|e| include.is_empty() || include.iter().any(|f| e.matches(f)))
+        .filter(|e| !exclude.iter().any(|f| e.matches(f)))
+        .collect()
+}
+
+/// Runs the entries of the matrix, the failures of the entries are reported in the returned cases
+pub async fn run(
+    args: Args,
+    registry: &[SuiteEntry],
+    ctx: SuiteContext,
+) -> Result<Vec<CaseReport>> {
+    let entries = Matrix::load(&args.matrix)?.entries();
+
+    // validate the whole matrix before running anything
+    for entry in &entries {
+        if !registry.iter().any(|s| s.name == entry.suite) {
+            return Err(anyhow!("unknown suite `{}` in matrix", entry.suite));
+        }
+    }
+
+    let entries = select(entries, &args.include, &args.exclude);
+
+    info!("###matrix###: {} entries to run", entries.len());
+
+    let mut cases = Vec::with_capacity(entries.len());
+    let shutdown = ctx.shutdown();
+    for entry in &entries {
+        if shutdown.is_requested() {
+            warn!(
+                "interrupted, {} entries of the matrix are not run",
+                entries.len() - cases.len()
+            );
+            break;
+        }
+        let name = entry.name();
+        info!("*******************************");
+        info!("Running test : {name}...");
+        info!("*******************************");
+
+        let case = run_entry(entry, registry, ctx.for_new_run()).await;
+        match (&case.failure, &case.skipped) {
+            (Some(e), _) => error!("Test {name} failed: {e}"),
+            (None, Some(reason)) => warn!("Test {name} skipped: {reason}"),
+            (None, None) => info!("Test {name} succeeded."),
+        }
+        cases.push(case);
+    }
+
+    let failed = cases.iter().filter(|c| c.failure.is_some()).count();
+    let skipped = cases.iter().filter(|c| c.skipped.is_some()).count();
+
+    info!("==================");
+    info!("=====SUMMARY======");
+    info!("==================");
+    for case in &cases {
+        match (&case.failure, &case.skipped) {
+            (Some(e), _) => info!("FAILED {} ({:.3}s): {e}", case.name, case.duration_secs),
+            (None, Some(reason)) => info!("SKIPPED {}: {reason}", case.name),
+            (None, None) => info!("PASSED {} ({:.3}s)", case.name, case.duration_secs),
+        }
+    }
+    info!("==================");
+    info!(
+        "{} passed, {failed} failed, {skipped} skipped",
+        cases.len() - failed - skipped
+    );
+
+    Ok(cases)
+}
+
+async fn run_entry(entry: &MatrixEntry, registry: &[SuiteEntry], ctx: SuiteContext) -> CaseReport {
+    let suite = registry
+        .iter()
+        .find(|s| s.name == entry.suite)
+        .expect("suites of the matrix are validated");
+    let fut = suite
+        .command()
+        .try_get_matches_from(std::iter::once(&entry.suite).chain(&entry.args))
+        .map_err(anyhow::Error::from)
+        .and_then(|matches| suite.start(&matches, ctx.clone()));
+    let fut = match fut {
+        Ok(fut) => fut,
+        Err(e) => Box::pin(async move { Err(e) }),
+    };
+    run_case(entry.name(), suite.name, fut, &ctx).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suite;
+
+    const MATRIX: &str = r#"
+[[suite]]
+name = "change-tracking"
+tags = ["stream"]
+args = [[], ["--append-only-stream"]]
+
+[[suite]]
+name = "explicit-txn"
+tags = ["txn", "stream"]
+
+[[suite]]
+name = "vacuum2"
+tags = ["vacuum", "txn"]
+args = [[], ["--explicit-txn"]]
+"#;
+
+    #[test]
+    fn expands_and_filters_the_matrix() {
+        let cases: &[(&[&str], &[&str], &[&str])] = &[
+            (
+                &[],
+                &[],
+                &[
+                    "change-tracking",
+                    "change-tracking --append-only-stream",
+                    "explicit-txn",
+                    "vacuum2",
+                    "vacuum2 --explicit-txn",
+                ],
+            ),
+            // by suite name
+            (&["vacuum2"], &[], &["vacuum2", "vacuum2 --explicit-txn"]),
+            // by tag
+            (
+                &["stream"],
+                &[],
+                &[
+                    "change-tracking",
+                    "change-tracking --append-only-stream",
+                    "explicit-txn",
+                ],
+            ),
+            (
+                &["txn"],
+                &[],
+                &["explicit-txn", "vacuum2", "vacuum2 --explicit-txn"],
+            ),
+            // by entry name
+            (
+                &["vacuum2 --explicit-txn"],
+                &[],
+                &["vacuum2 --explicit-txn"],
+            ),
+            (
+                &["vacuum", "explicit-txn"],
+                &["vacuum2 --explicit-txn"],
+                &["explicit-txn", "vacuum2"],
+            ),
+            (
+                &[],
+                &["txn", "change-tracking --append-only-stream"],
+                &["change-tracking"],
+            ),
+            (&["no-such-tag"], &[], &[]),
+        ];
+        for (include, exclude, expected) in cases {
+            let matrix: Matrix = toml::from_str(MATRIX).unwrap();
+            let strings =
+                |filters: &[&str]| filters.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+            let names: Vec<_> = select(matrix.entries(), &strings(include), &strings(exclude))
+                .iter()
+                .map(MatrixEntry::name)
+                .collect();
+            assert_eq!(names, *expected, "include {include:?}, exclude {exclude:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = toml::from_str::<Matrix>("[[suite]]\nname = \"task\"\nflags = []").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `flags`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn matrix_covers_every_suite() {
+        let entries = Matrix::load(&PathBuf::from("matrix.toml"))
+            .unwrap()
+            .entries();
+        for suite in suite::registry() {
+            assert!(
+                entries.iter().any(|entry| entry.suite == suite.name),
+                "{} is not in the matrix",
+                suite.name
+            );
+        }
+        for entry in &entries {
+            let suite = suite::registry()
+                .into_iter()
+                .find(|s| s.name == entry.suite)
+                .unwrap();
+            suite
+                .command()
+                .try_get_matches_from(std::iter::once(&entry.suite).chain(&entry.args))
+                .unwrap();
+        }
+    }
+}
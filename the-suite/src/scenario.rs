@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use databend_driver::Connection;
+use log::info;
+
+use crate::errors::error_code;
+use crate::suite::SuiteContext;
+use crate::util::ConnectionExt;
+
+/// A multi-session scenario, read from a sqllogictest-style file, e.g.
+///
+/// ```text
+/// # comments start with `#`
+/// statement ok @c1
+/// BEGIN
+///
+/// statement error @c2
+/// SELECT * FROM no_such_table
+///
+/// statement error 1025 @c2
+/// SELECT * FROM no_such_table
+///
+/// query @c1
+/// SELECT c FROM t ORDER BY c
+/// ----
+/// 1
+/// 2
+///
+/// query rowsort @c2
+/// SELECT c, d FROM t
+/// ----
+/// 2 b
+/// 1 a
+/// ```
+///
+/// Records are separated by blank lines. Each statement runs in the session named by its
+/// `@tag`, sessions are opened on first use and are kept for the whole scenario. The expected
+/// rows of a query are listed one per line with their columns separated by a space, `query`
+/// expects them in order and `query rowsort` in any order. An empty result is an empty list.
+#[derive(Debug)]
+pub struct Scenario {
+    pub path: PathBuf,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    session: String,
+    sql: String,
+    expect: Expectation,
+    /// Line of the header of the record in the file
+    line: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Expectation {
+    Ok,
+    /// Fails, with the given error code if any
+    Error(Option<u16>),
+    Rows {
+        rows: Vec<String>,
+        sorted: bool,
+    },
+}
+
+impl Scenario {
+    /// Loads a scenario file, or all the `*.slt` files of a directory ordered by name
+    pub fn load_all(path: &Path) -> Result<Vec<Self>> {
+        if !path.is_dir() {
+            return Ok(vec![Self::load(path)?]);
+        }
+        let mut files = vec![];
+        for entry in std::fs::read_dir(path)
+            .with_context(|| format!("failed to read scenario dir {}", path.display()))?
+        {
+            let file = entry?.path();
+            if file.extension().is_some_and(|ext| ext == "slt") {
+                files.push(file);
+            }
+        }
+        files.sort();
+        files.iter().map(|file| Self::load(file)).collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scenario file {}", path.display()))?;
+        let steps =
+            parse(&content).with_context(|| format!("invalid scenario file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            steps,
+        })
+    }
+
+    /// Runs the steps one by one, every session uses `database`
+    pub async fn run(&self, ctx: &SuiteContext, database: &str) -> Result<()> {
+        info!("===== scenario {} =====", self.path.display());
+        let mut sessions: HashMap<&str, Box<dyn Connection>> = HashMap::new();
+        for step in &self.steps {
+            if !sessions.contains_key(step.session.as_str()) {
+                let conn = ctx.new_connection().await?;
+                conn.exec(&format!("use {database}")).await?;
+                sessions.insert(&step.session, conn);
+            }
+            let conn = &sessions[step.session.as_str()];
+            step.run(conn.as_ref()).await.with_context(|| {
+                format!(
+                    "{}:{}: @{} {}",
+                    self.path.display(),
+                    step.line,
+                    step.session,
+                    step.sql
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Step {
+    async fn run(&self, conn: &dyn Connection) -> Result<()> {
+        match &self.expect {
+            Expectation::Ok => {
+                conn.exec(&self.sql).await?;
+            }
+            Expectation::Error(code) => match conn.exec(&self.sql).await {
+                Ok(_) => return Err(anyhow!("statement succeeded, but an error is expected")),
+                Err(e) => {
+                    if let Some(code) = code {
+                        if error_code(&e) != Some(*code) {
+                            return Err(anyhow!("expected error code {code}, got: {e}"));
+                        }
+                    }
+                }
+            },
+            Expectation::Rows { rows, sorted } => {
+                let mut actual: Vec<String> = conn
+                    .query_strings(&self.sql)
+                    .await?
+                    .into_iter()
+                    .map(|row| row.join(" "))
+                    .collect();
+                let mut expected = rows.clone();
+                if *sorted {
+                    actual.sort();
+                    expected.sort();
+                }
+                if actual != expected {
+                    return Err(anyhow!(
+                        "unexpected result\nexpected:\n{}\nactual:\n{}",
+                        expected.join("\n"),
+                        actual.join("\n")
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse(content: &str) -> Result<Vec<Step>> {
+    let mut steps = vec![];
+    let mut lines = content.lines().enumerate().peekable();
+    while let Some((idx, line)) = lines.next() {
+        let line_no = idx + 1;
+        let header = line.trim();
+        if header.is_empty() || header.starts_with('#') {
+            continue;
+        }
+
+        let (words, session) = match header.rsplit_once('@') {
+            Some((words, session)) if !session.trim().is_empty() => {
+                (words.split_whitespace().collect::<Vec<_>>(), session.trim())
+            }
+            _ => return Err(anyhow!("line {line_no}: missing session tag in `{header}`")),
+        };
+        let is_query = match words.first() {
+            Some(&"statement") => false,
+            Some(&"query") => true,
+            _ => return Err(anyhow!("line {line_no}: unknown record `{header}`")),
+        };
+
+        // the sql lasts until a blank line, or the `----` separator of a query
+        let mut sql = vec![];
+        let mut has_result = false;
+        while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+            if line.trim() == "----" {
+                has_result = true;
+                break;
+            }
+            sql.push(line);
+        }
+        if sql.is_empty() {
+            return Err(anyhow!("line {line_no}: missing sql"));
+        }
+
+        let expect = match (is_query, &words[1..]) {
+            (false, ["ok"]) => Expectation::Ok,
+            (false, ["error"]) => Expectation::Error(None),
+            (false, ["error", code]) => Expectation::Error(Some(
+                code.parse()
+                    .map_err(|_| anyhow!("line {line_no}: invalid error code `{code}`"))?,
+            )),
+            (true, [] | ["rowsort"]) => {
+                if !has_result {
+                    return Err(anyhow!("line {line_no}: missing `----` of the query"));
+                }
+                let mut rows = vec![];
+                while let Some((_, line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+                    rows.push(line.trim().to_owned());
+                }
+                Expectation::Rows {
+                    rows,
+                    sorted: words.len() == 2,
+                }
+            }
+            _ => return Err(anyhow!("line {line_no}: unknown record `{header}`")),
+        };
+        if has_result && !is_query {
+            return Err(anyhow!("line {line_no}: unexpected `----` of a statement"));
+        }
+
+        steps.push(Step {
+            session: session.to_owned(),
+            sql: sql.join("\n"),
+            expect,
+            line: line_no,
+        });
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{string, MockServer, Reply};
+
+    fn step(line: usize, session: &str, sql: &str, expect: Expectation) -> Step {
+        Step {
+            session: session.to_owned(),
+            sql: sql.to_owned(),
+            expect,
+            line,
+        }
+    }
+
+    fn error(content: &str) -> String {
+        parse(content).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_the_records() {
+        let content = "# a comment\n\
+                       statement ok @c1\n\
+                       BEGIN\n\
+                       \n\
+                       statement error @c2\n\
+                       SELECT *\n\
+                       FROM no_such_table\n\
+                       \n\
+                       statement error 1025 @c2\n\
+                       SELECT * FROM no_such_table\n\
+                       \n\
+                       query @c1\n\
+                       SELECT c FROM t\n\
+                       ----\n\
+                       1\n\
+                       2\n\
+                       \n\
+                       query rowsort @c2\n\
+                       SELECT c, d FROM t\n\
+                       ----\n\
+                       2 b\n\
+                       1 a\n\
+                       \n\
+                       query @c2\n\
+                       SELECT c FROM empty\n\
+                       ----\n\
+                       \n\
+                       statement ok @c1\n\
+                       COMMIT";
+        let rows = |rows: &[&str], sorted| Expectation::Rows {
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+            sorted,
+        };
+        assert_eq!(
+            parse(content).unwrap(),
+            [
+                step(2, "c1", "BEGIN", Expectation::Ok),
+                step(
+                    5,
+                    "c2",
+                    "SELECT *\nFROM no_such_table",
+                    Expectation::Error(None)
+                ),
+                step(
+                    9,
+                    "c2",
+                    "SELECT * FROM no_such_table",
+                    Expectation::Error(Some(1025))
+                ),
+                step(12, "c1", "SELECT c FROM t", rows(&["1", "2"], false)),
+                step(18, "c2", "SELECT c, d FROM t", rows(&["2 b", "1 a"], true)),
+                step(24, "c2", "SELECT c FROM empty", rows(&[], false)),
+                // the last record needs no blank line
+                step(28, "c1", "COMMIT", Expectation::Ok),
+            ]
+        );
+        assert_eq!(parse("query @c1\nSELECT 1\n----\n1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        assert_eq!(
+            error("statement ok\nBEGIN"),
+            "line 1: missing session tag in `statement ok`"
+        );
+        assert_eq!(
+            error("statement ok @\nBEGIN"),
+            "line 1: missing session tag in `statement ok @`"
+        );
+        assert_eq!(
+            error("SELECT 1 @c1\n"),
+            "line 1: unknown record `SELECT 1 @c1`"
+        );
+        assert_eq!(
+            error("statement @c1\nBEGIN"),
+            "line 1: unknown record `statement @c1`"
+        );
+        assert_eq!(
+            error("statement ok @c1\nBEGIN\n\nquery @c1\nSELECT 1\n1"),
+            "line 4: missing `----` of the query"
+        );
+        assert_eq!(
+            error("statement ok @c1\nSELECT 1\n----\n1"),
+            "line 1: unexpected `----` of a statement"
+        );
+        assert_eq!(
+            error("statement error abc @c1\nBEGIN"),
+            "line 1: invalid error code `abc`"
+        );
+        assert_eq!(error("statement ok @c1\n\nBEGIN"), "line 1: missing sql");
+    }
+
+    /// Runs the scenarios of the suite against a mock replying what each step expects, in order
+    #[tokio::test]
+    async fn runs_the_scenarios_of_the_suite() {
+        let scenarios = Scenario::load_all(Path::new("sql/explicit_txn")).unwrap();
+        assert!(!scenarios.is_empty());
+        let mock = MockServer::new();
+        for scenario in &scenarios {
+            for step in &scenario.steps {
+                let reply = match &step.expect {
+                    Expectation::Ok => Reply::ok(),
+                    Expectation::Error(code) => Reply::error(code.unwrap_or(1025), "expected"),
+                    Expectation::Rows { rows, .. } => Reply::rows(
+                        rows.iter()
+                            .map(|row| row.split(' ').map(string).collect())
+                            .collect(),
+                    ),
+                };
+                mock.when(&step.sql, reply.times(1));
+            }
+        }
+        let ctx = mock.context();
+        for scenario in &scenarios {
+            scenario.run(&ctx, "test_txn").await.unwrap();
+        }
+
+        let steps: usize = scenarios.iter().map(|scenario| scenario.steps.len()).sum();
+        let sessions: usize = scenarios
+            .iter()
+            .map(|scenario| {
+                let mut sessions: Vec<_> = scenario.steps.iter().map(|s| &s.session).collect();
+                sessions.sort();
+                sessions.dedup();
+                sessions.len()
+            })
+            .sum();
+        assert_eq!(mock.count("use test_txn"), sessions);
+        assert_eq!(mock.connections(), sessions);
+        assert_eq!(mock.statements().len(), steps + sessions);
+
+        // a reply the scenario does not expect fails it, with the location of the step
+        let scenario = &scenarios[0];
+        let mock = MockServer::new();
+        let error = scenario.run(&mock.context(), "test_txn").await.unwrap_err();
+        assert!(
+            format!("{error:#}").contains("unexpected result"),
+            "{error:#}"
+        );
+        assert!(error
+            .to_string()
+            .starts_with("sql/explicit_txn/01_isolation.slt:"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::sync::watch;
+
+/// Requests the suites of a run to stop early, e.g. on Ctrl-C. The workers stop issuing
+/// operations, and the suites verify whatever state they leave behind
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    requested: Arc<watch::Sender<bool>>,
+    /// How long the execute phase of a suite is waited for once a shutdown is requested
+    grace: Duration,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            requested: Arc::new(watch::Sender::new(false)),
+            grace: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn with_grace(self, grace: Duration) -> Self {
+        Self { grace, ..self }
+    }
+
+    pub fn request(&self) {
+        self.requested.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Completes once a shutdown is requested
+    pub async fn requested(&self) {
+        let mut rx = self.requested.subscribe();
+        // the sender is owned by `self`, the channel cannot be closed
+        let _ = rx.wait_for(|requested| *requested).await;
+    }
+
+    /// Runs `fut` to completion, unless a shutdown is requested and it is not done within the
+    /// grace period, in which case it is dropped and `None` is returned
+    pub async fn grace<T>(&self, fut: impl Future<Output = T>) -> Option<T> {
+        tokio::pin!(fut);
+        tokio::select! {
+            result = &mut fut => return Some(result),
+            _ = self.requested() => {}
+        }
+        tokio::time::timeout(self.grace, fut).await.ok()
+    }
+
+    /// Requests a shutdown on the first Ctrl-C or SIGTERM, and exits on the second one
+    pub fn listen_for_signals(&self) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            signal().await;
+            warn!(
+                "interrupted, stopping the workers within {:?}, interrupt again to exit now",
+                shutdown.grace
+            );
+            shutdown.request();
+            signal().await;
+            warn!("interrupted again, exiting");
+            std::process::exit(130);
+        });
+    }
+}
+
+async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                info!("failed to listen for SIGTERM: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn grace_period_after_the_request() {
+        let shutdown = Shutdown::default().with_grace(Duration::from_secs(5));
+        let slow = tokio::time::sleep(Duration::from_secs(60));
+        assert_eq!(shutdown.grace(async { 1 }).await, Some(1));
+
+        let requester = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            requester.request();
+        });
+        let start = tokio::time::Instant::now();
+        assert_eq!(shutdown.grace(slow).await, None);
+        assert_eq!(start.elapsed(), Duration::from_secs(6));
+        assert!(shutdown.is_requested());
+    }
+}
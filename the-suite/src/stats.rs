@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use hdrhistogram::Histogram;
+use log::{error, warn};
+use serde::Serialize;
+
+use crate::errors::{ErrorCategory, OpError};
+
+/// Number of successful and failed operations of a kind
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct OpCounts {
+    pub success: u64,
+    pub failed: u64,
+    /// Attempts of the operations, the retried ones included
+    pub attempts: u64,
+}
+
+/// Latencies of the attempts of the operations of a kind, in microseconds
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+    /// Attempts per second over the execute phase
+    pub ops_per_sec: f64,
+}
+
+/// Operations issued by a suite run, keyed by kind of operation, e.g. `insert`, `compact`
+#[derive(Default)]
+pub struct Stats {
+    ops: Mutex<BTreeMap<String, OpCounts>>,
+    latencies: Mutex<BTreeMap<String, Histogram<u64>>>,
+    errors: Mutex<BTreeMap<ErrorCategory, u64>>,
+    /// Categories of the errors the operations may fail with, any if not set
+    allowed: OnceLock<Vec<ErrorCategory>>,
+    /// Number of the errors outside of the allowed categories, and the first of them
+    unexpected: Mutex<(u64, Option<String>)>,
+    /// Duration of the execute phase of the run, which issues the operations of the workload
+    execution: Mutex<Option<Duration>>,
+}
+
+impl Stats {
+    pub fn record<T, E: OpError>(&self, kind: &str, result: &Result<T, E>) {
+        let mut ops = self.ops.lock().unwrap();
+        let counts = ops.entry(kind.to_owned()).or_default();
+        counts.attempts += 1;
+        match result {
+            Ok(_) => counts.success += 1,
+            Err(e) => {
+                counts.failed += 1;
+                self.record_error(kind, e);
+            }
+        }
+    }
+
+    /// Runs an operation, recording its outcome and latency
+    pub async fn timed<T, E: OpError>(
+        &self,
+        kind: &str,
+        op: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        self.timed_from(kind, Instant::now(), op).await
+    }
+
+    /// Like [`Stats::timed`], the latency is measured from `start`, e.g. the start of the
+    /// operation in the schedule of an open loop
+    pub async fn timed_from<T, E: OpError>(
+        &self,
+        kind: &str,
+        start: Instant,
+        op: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let result = op.await;
+        self.record_latency(kind, start.elapsed());
+        self.record(kind, &result);
+        result
+    }
+
+    /// Latency of an attempt of an operation
+    pub fn record_latency(&self, kind: &str, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        // 1us up to an hour, longer latencies are recorded as an hour
+        let histogram = latencies.entry(kind.to_owned()).or_insert_with(|| {
+            Histogram::new_with_bounds(1, 3_600_000_000, 3).expect("bounds are valid")
+        });
+        histogram.saturating_record(latency.as_micros() as u64);
+    }
+
+    /// A failed attempt of an operation, which is retried
+    pub fn record_retried<E: OpError>(&self, kind: &str, e: &E) {
+        let mut ops = self.ops.lock().unwrap();
+        ops.entry(kind.to_owned()).or_default().attempts += 1;
+        *self.errors.lock().unwrap().entry(e.category()).or_default() += 1;
+    }
+
+    fn record_error<E: OpError>(&self, kind: &str, e: &E) {
+        let category = e.category();
+        *self.errors.lock().unwrap().entry(category).or_default() += 1;
+        let Some(allowed) = self.allowed.get() else {
+            return;
+        };
+        if !allowed.contains(&category) {
+            error!("{kind} failed with an unexpected {category} error: {e}");
+            let mut unexpected = self.unexpected.lock().unwrap();
+            unexpected.0 += 1;
+            unexpected.1.get_or_insert_with(|| format!("{kind}: {e}"));
+        }
+    }
+
+    /// Fails the operations whose errors are not of `categories` from now on, once per run
+    pub fn allow_errors(&self, categories: &[ErrorCategory]) {
+        if self.allowed.set(categories.to_vec()).is_err() {
+            warn!("the allowed error categories are already set");
+        }
+    }
+
+    /// Fails if an operation failed with an error outside of the allowed categories
+    pub fn check_errors(&self) -> Result<()> {
+        let (count, first) = &*self.unexpected.lock().unwrap();
+        match first {
+            None => Ok(()),
+            Some(first) => {
+                let allowed = self.allowed.get().map(Vec::as_slice).unwrap_or_default();
+                let allowed: Vec<_> = allowed.iter().map(ErrorCategory::as_str).collect();
+                Err(anyhow!(
+                    "{count} operations failed with errors not allowed (allowed: [{}]), the first: {first}",
+                    allowed.join(", ")
+                ))
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, OpCounts> {
+        self.ops.lock().unwrap().clone()
+    }
+
+    pub fn record_execution(&self, duration: Duration) {
+        *self.execution.lock().unwrap() = Some(duration);
+    }
+
+    /// Latency percentiles of the operations by kind, the throughput is over the execute phase,
+    /// or `elapsed` if it did not complete
+    pub fn latencies(&self, elapsed: Duration) -> BTreeMap<String, LatencySummary> {
+        let elapsed = self.execution.lock().unwrap().unwrap_or(elapsed);
+        let latencies = self.latencies.lock().unwrap();
+        latencies
+            .iter()
+            .map(|(kind, h)| {
+                let summary = LatencySummary {
+                    count: h.len(),
+                    p50_us: h.value_at_quantile(0.5),
+                    p90_us: h.value_at_quantile(0.9),
+                    p99_us: h.value_at_quantile(0.99),
+                    max_us: h.max(),
+                    ops_per_sec: h.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+                };
+                (kind.clone(), summary)
+            })
+            .collect()
+    }
+
+    /// Number of the failed operations by category of error
+    pub fn errors(&self) -> BTreeMap<ErrorCategory, u64> {
+        self.errors.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latencies_and_throughput_by_kind() {
+        let stats = Stats::default();
+        for ms in 1..=100 {
+            stats.record_latency("insert", Duration::from_millis(ms));
+        }
+        stats.record_latency("vacuum2", Duration::from_secs(2));
+
+        // over the whole run until the execute phase is done
+        let latencies = stats.latencies(Duration::from_secs(50));
+        assert_eq!(latencies["insert"].ops_per_sec, 2.0);
+
+        stats.record_execution(Duration::from_secs(10));
+        let latencies = stats.latencies(Duration::from_secs(50));
+        let insert = &latencies["insert"];
+        assert_eq!(insert.count, 100);
+        // within the 3 significant digits of the histograms
+        let ms = |us: u64, ms: f64| (us as f64 / 1000.0 - ms).abs() <= ms / 1000.0;
+        assert!(ms(insert.p50_us, 50.0), "{insert:?}");
+        assert!(ms(insert.p90_us, 90.0), "{insert:?}");
+        assert!(ms(insert.p99_us, 99.0), "{insert:?}");
+        assert!(ms(insert.max_us, 100.0), "{insert:?}");
+        assert_eq!(insert.ops_per_sec, 10.0);
+        let vacuum = &latencies["vacuum2"];
+        assert_eq!(vacuum.count, 1);
+        assert!(ms(vacuum.max_us, 2000.0), "{vacuum:?}");
+        assert_eq!(vacuum.ops_per_sec, 0.1);
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use clap::{ArgMatches, Command, Id};
+use databend_driver::{Client, Connection};
+use futures_util::future::BoxFuture;
+use log::{error, info, warn};
+
+use crate::auto_vacuum::AutoVacuumSuite;
+use crate::change_tracking::ChangeTrackingSuite;
+use crate::crash::Crasher;
+use crate::datagen::DataGen;
+use crate::errors::ErrorCategory;
+use crate::explict_txn::ExplicitTxnSuite;
+use crate::fault_proxy::FaultProxy;
+use crate::forensics;
+use crate::multi_table_insert::MultiTableInsertSuite;
+use crate::namespace;
+use crate::nodes::{Nodes, Role};
+use crate::preflight::{Requirement, ServerInfo};
+use crate::reconcile::QueryTracker;
+use crate::record::{Recorder, RecordingConnector};
+use crate::retry::RetryPolicy;
+use crate::shutdown::Shutdown;
+use crate::stats::Stats;
+use crate::task::TaskSuite;
+use crate::upsert::{MergeIntoSuite, ReplaceIntoSuite};
+use crate::vacuum::VacuumSuite;
+use crate::vacuum2::Vacuum2Suite;
+
+/// Opens the connections of the suites, a [`Client`] of the server under test,
+/// or a fake server in unit tests
+#[async_trait]
+pub trait Connector: Send + Sync {
+    async fn connect(&self) -> Result<Box<dyn Connection>>;
+}
+
+#[async_trait]
+impl Connector for Client {
+    async fn connect(&self) -> Result<Box<dyn Connection>> {
+        let conn = self.get_conn().await?;
+        Ok(conn)
+    }
+}
+
+/// Everything a suite needs from the harness, cheap to clone into spawned workers
+#[derive(Clone)]
+pub struct SuiteContext {
+    /// The query nodes the connections are spread over
+    nodes: Arc<Nodes>,
+    stats: Arc<Stats>,
+    seed: u64,
+    /// Session settings of the new connections, as SQL literals
+    settings: Arc<BTreeMap<String, String>>,
+    /// Overrides the error categories allowed by the suites
+    allowed_errors: Option<Arc<[ErrorCategory]>>,
+    retry_policy: Arc<RetryPolicy>,
+    shutdown: Shutdown,
+    /// Namespace of the databases of the run, see [`SuiteContext::database`]
+    run_id: Arc<str>,
+    /// Databases of the run, dropped once it is done unless `keep`
+    databases: Arc<Mutex<BTreeSet<String>>>,
+    keep: bool,
+    /// Skips the teardown of the failed runs, to inspect what they left behind
+    keep_on_failure: bool,
+    /// Where the forensics bundles of the failed runs are collected, none if `None`
+    forensics_dir: Option<Arc<Path>>,
+    /// What the server offers, the suites requiring anything else are skipped. Unchecked if `None`
+    server: Option<Arc<ServerInfo>>,
+    /// Tags the statements of the run with query ids, see [`QueryTracker`]
+    queries: Option<Arc<QueryTracker>>,
+    /// Inject network faults while the suites execute their workloads, one per node
+    fault_proxies: Vec<Arc<FaultProxy>>,
+    /// Crashes the launched server while the suites execute their workloads
+    crasher: Option<Arc<Crasher>>,
+}
+
+impl SuiteContext {
+    /// Context of the query nodes of `dsns`, see [`SuiteContext::with_connectors`]
+    pub fn new(dsns: Vec<String>) -> Self {
+        let connectors = dsns
+            .into_iter()
+            .map(|dsn| Arc::new(Client::new(dsn)) as Arc<dyn Connector>)
+            .collect();
+        Self::with_connectors(connectors)
+    }
+
+    /// Context of several query nodes, the connections are opened on each of them in turn
+    pub fn with_connectors(connectors: Vec<Arc<dyn Connector>>) -> Self {
+        Self {
+            nodes: Arc::new(Nodes::new(connectors)),
+            stats: Arc::new(Stats::default()),
+            seed: 0,
+            settings: Arc::default(),
+            allowed_errors: None,
+            retry_policy: Arc::default(),
+            shutdown: Shutdown::default(),
+            run_id: namespace::new_run_id().into(),
+            databases: Arc::default(),
+            keep: false,
+            keep_on_failure: false,
+            forensics_dir: None,
+            server: None,
+            queries: None,
+            fault_proxies: vec![],
+            crasher: None,
+        }
+    }
+
+    /// Seed of the data generated by the suites
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Generator of the rows of a worker, each concurrent worker should use a `stream` of its own
+    pub fn datagen(&self, stream: u64) -> DataGen {
+        DataGen::new(self.seed, stream)
+    }
+
+    /// Session settings of the new connections, overriding the previous ones of the same names
+    pub fn with_settings(self, settings: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut merged = (*self.settings).clone();
+        merged.extend(settings);
+        Self {
+            settings: Arc::new(merged),
+            ..self
+        }
+    }
+
+    /// Session settings of the new connections, unless they are already set
+    pub fn with_default_settings(self, settings: &[(&str, &str)]) -> Self {
+        let mut merged = (*self.settings).clone();
+        for (name, value) in settings {
+            merged
+                .entry(name.to_string())
+                .or_insert_with(|| value.to_string());
+        }
+        Self {
+            settings: Arc::new(merged),
+            ..self
+        }
+    }
+
+    /// Categories of the errors the operations of any suite may fail with, instead of its own
+    pub fn with_allowed_errors(self, categories: Vec<ErrorCategory>) -> Self {
+        Self {
+            allowed_errors: Some(categories.into()),
+            ..self
+        }
+    }
+
+    /// How the workload operations are retried
+    pub fn with_retry_policy(self, policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Arc::new(policy),
+            ..self
+        }
+    }
+
+    pub fn retry_policy(&self) -> Arc<RetryPolicy> {
+        self.retry_policy.clone()
+    }
+
+    /// Stops the suites of this context early once requested, shared by all the runs
+    pub fn with_shutdown(self, shutdown: Shutdown) -> Self {
+        Self { shutdown, ..self }
+    }
+
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Name of the database `name` of this run, which is dropped once the run is done
+    pub fn database(&self, name: &str) -> String {
+        let database = namespace::database_name(&self.run_id, name);
+        self.databases.lock().unwrap().insert(database.clone());
+        database
+    }
+
+    /// Keeps the databases of the runs instead of dropping them
+    pub fn with_keep(self, keep: bool) -> Self {
+        Self { keep, ..self }
+    }
+
+    /// Skips the teardown of the failed runs, including the drop of their databases
+    pub fn with_keep_on_failure(self, keep_on_failure: bool) -> Self {
+        Self {
+            keep_on_failure,
+            ..self
+        }
+    }
+
+    /// Collects a forensics bundle of every failed run into `dir`, see [`forensics::collect`]
+    pub fn with_forensics_dir(self, dir: Option<PathBuf>) -> Self {
+        Self {
+            forensics_dir: dir.map(Into::into),
+            ..self
+        }
+    }
+
+    /// Skips the suites whose requirements `server` lacks, see [`crate::preflight`]
+    pub fn with_server(self, server: ServerInfo) -> Self {
+        Self {
+            server: Some(Arc::new(server)),
+            ..self
+        }
+    }
+
+    /// Tags the statements of the runs with query ids, to reconcile their outcomes with the
+    /// query log of the server once they are done
+    pub fn with_query_tracking(self, enabled: bool) -> Self {
+        Self {
+            queries: enabled.then(|| Arc::new(QueryTracker::new(&self.run_id))),
+            ..self
+        }
+    }
+
+    pub fn query_tracker(&self) -> Option<Arc<QueryTracker>> {
+        self.queries.clone()
+    }
+
+    /// Injects the faults of `proxy` too while the suites execute, the connections to its node are
+    /// expected to go through it
+    pub fn with_fault_proxy(mut self, proxy: Arc<FaultProxy>) -> Self {
+        self.fault_proxies.push(proxy);
+        self
+    }
+
+    /// Opens the connections of the workers of the roles on the assigned nodes, by their positions
+    /// from 1, instead of round-robin
+    pub fn with_assignments(self, assignments: &[(Role, usize)]) -> Result<Self> {
+        Ok(Self {
+            nodes: Arc::new(self.nodes.with_assignments(assignments)?),
+            ..self
+        })
+    }
+
+    /// Crashes the nodes of the launched server while the suites supporting it execute, see
+    /// [`Suite::CRASH_RECOVERY`]
+    pub fn with_crasher(self, crasher: Arc<Crasher>) -> Self {
+        Self {
+            crasher: Some(crasher),
+            ..self
+        }
+    }
+
+    /// Databases of this run so far
+    pub fn databases(&self) -> Vec<String> {
+        self.databases.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Drops the databases of this run, unless they are kept
+    pub async fn drop_databases(&self) -> Result<()> {
+        let databases = self.databases();
+        if databases.is_empty() {
+            return Ok(());
+        }
+        if self.keep {
+            info!("keeping databases {}", databases.join(", "));
+            return Ok(());
+        }
+        let conn = self.new_connection().await?;
+        for database in databases {
+            conn.exec(&format!("DROP DATABASE IF EXISTS {database}"))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Records all the statements of the connections of this context into the trace of `recorder`
+    pub fn with_recorder(self, recorder: Arc<Recorder>) -> Self {
+        Self {
+            nodes: Arc::new(
+                self.nodes.map(|connector| {
+                    Arc::new(RecordingConnector::new(connector, recorder.clone()))
+                }),
+            ),
+            ..self
+        }
+    }
+
+    /// Context of another suite run, which collects its own stats into databases of its own
+    pub fn for_new_run(&self) -> Self {
+        let run_id = namespace::new_run_id();
+        Self {
+            stats: Arc::new(Stats::default()),
+            queries: self
+                .queries
+                .as_ref()
+                .map(|_| Arc::new(QueryTracker::new(&run_id))),
+            run_id: run_id.into(),
+            databases: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    /// Opens a connection on the next node, with the session settings of the context
+    pub async fn new_connection(&self) -> Result<Box<dyn Connection>> {
+        self.connect(self.nodes.connector(None)).await
+    }
+
+    /// Opens a connection of a worker of `role`, on its node if assigned one
+    pub async fn new_connection_as(&self, role: Role) -> Result<Box<dyn Connection>> {
+        self.connect(self.nodes.connector(Some(role))).await
+    }
+
+    /// Opens a connection on every node, in order
+    pub async fn node_connections(&self) -> Result<Vec<Box<dyn Connection>>> {
+        let mut conns = vec![];
+        for connector in self.nodes.connectors() {
+            conns.push(self.connect(connector).await?);
+        }
+        Ok(conns)
+    }
+
+    async fn connect(&self, connector: &Arc<dyn Connector>) -> Result<Box<dyn Connection>> {
+        let mut conn = connector.connect().await?;
+        if let Some(queries) = &self.queries {
+            conn = queries.track(conn);
+        }
+        for (name, value) in self.settings.iter() {
+            conn.exec(&format!("SET {name} = {value}"))
+                .await
+                .with_context(|| format!("failed to set {name} = {value}"))?;
+        }
+        Ok(conn)
+    }
+}
+
+/// A test scenario, run by the harness phase by phase:
+/// `setup` -> `execute` -> `verify`, and `teardown` no matter how the other phases end, followed by
+/// the drop of the databases of the run, unless a failed run is kept by `--keep-on-failure`.
+/// The state of a failed run is collected into a forensics bundle before its teardown.
+/// On a shutdown the workers of `execute` are given a grace period to stop, see [`Shutdown`],
+/// then whatever state they leave behind is verified. The network faults of `--faults` and the
+/// crashes of `--crash-every` are only injected while executing
+#[async_trait]
+pub trait Suite: Send + Sync + Sized + 'static {
+    /// Command line arguments of the suite
+    type Args: clap::Args + Debug + Send;
+
+    /// Name of the suite, which is also the name of its subcommand
+    const NAME: &'static str;
+
+    /// One line description of the suite
+    const DESCRIPTION: &'static str;
+
+    /// Other names the subcommand is known by
+    const ALIASES: &'static [&'static str] = &[];
+
+    /// Session settings the suite relies on, (name, SQL literal), unless configured otherwise
+    const SETTINGS: &'static [(&'static str, &'static str)] = &[];
+
+    /// Categories of the errors its operations may fail with, the suite fails on any other
+    const ALLOWED_ERRORS: &'static [ErrorCategory] = &[ErrorCategory::Conflict];
+
+    /// What the suite needs from the server, it is skipped on a server lacking any of them
+    const REQUIREMENTS: &'static [Requirement] = &[];
+
+    /// Whether the suite runs with the crashes of `--crash-every`, see [`Suite::committed_rows`]
+    const CRASH_RECOVERY: bool = false;
+
+    fn new(args: Self::Args, ctx: SuiteContext) -> Self;
+
+    async fn setup(&self) -> Result<()>;
+
+    async fn execute(&self) -> Result<()>;
+
+    async fn verify(&self) -> Result<()>;
+
+    async fn teardown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Checks that the tables of the suite are healthy after a crash of the server, and counts
+    /// their committed rows, which must never go backwards
+    async fn committed_rows(&self) -> Result<u64> {
+        Err(anyhow!("{} does not support crash recovery", Self::NAME))
+    }
+}
+
+/// Error of a suite not run since the server lacks what it requires, reported as skipped
+#[derive(Debug)]
+pub struct Skipped(pub String);
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "skipped: {}", self.0)
+    }
+}
+
+impl std::error::Error for Skipped {}
+
+type RunFn = fn(&ArgMatches, SuiteContext) -> Result<BoxFuture<'static, Result<()>>>;
+
+/// A type-erased suite in the registry
+pub struct SuiteEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    aliases: &'static [&'static str],
+    pub requirements: &'static [Requirement],
+    augment_args: fn(Command) -> Command,
+    /// Default values of the arguments, from the configuration file
+    defaults: Vec<(Id, Vec<String>)>,
+    run: RunFn,
+}
+
+impl SuiteEntry {
+    pub fn of<S: Suite>() -> Self {
+        Self {
+            name: S::NAME,
+            description: S::DESCRIPTION,
+            aliases: S::ALIASES,
+            requirements: S::REQUIREMENTS,
+            augment_args: <S::Args as clap::Args>::augment_args,
+            defaults: vec![],
+            run: |matches, ctx| {
+                let args = <S::Args as clap::FromArgMatches>::from_arg_matches(matches)?;
+                info!("###options###: \n {:#?}", args);
+                let missing = ctx.server.as_ref().and_then(|s| s.missing(S::REQUIREMENTS));
+                if let Some(reason) = missing {
+                    warn!("===== [{}] {} =====", S::NAME, Skipped(reason.clone()));
+                    return Ok(Box::pin(async move { Err(Skipped(reason).into()) }));
+                }
+                let ctx = ctx.with_default_settings(S::SETTINGS);
+                let stats = ctx.stats();
+                let allowed = ctx.allowed_errors.as_deref().unwrap_or(S::ALLOWED_ERRORS);
+                let mut allowed = allowed.to_vec();
+                if ctx.crasher.is_some() && S::CRASH_RECOVERY {
+                    // the statements in flight on a crashed node
+                    allowed.push(ErrorCategory::Connection);
+                }
+                if !ctx.fault_proxies.is_empty() {
+                    // the statements whose responses were dropped, reset or stalled by a proxy
+                    allowed.extend([ErrorCategory::Connection, ErrorCategory::Timeout]);
+                }
+                stats.allow_errors(&allowed);
+                let suite = S::new(args, ctx.clone());
+                Ok(Box::pin(async move {
+                    run_suite(suite, &ctx).await?;
+                    stats.check_errors()
+                }))
+            },
+        }
+    }
+
+    /// Overrides the default values of the arguments, by their long names
+    pub fn with_defaults(mut self, defaults: Vec<(String, Vec<String>)>) -> Result<Self> {
+        let command = self.command();
+        for (long, values) in defaults {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long.as_str()))
+                .ok_or_else(|| anyhow!("suite `{}` has no argument `--{long}`", self.name))?;
+            self.defaults.push((arg.get_id().clone(), values));
+        }
+        Ok(self)
+    }
+
+    /// The subcommand of this suite
+    pub fn command(&self) -> Command {
+        let command = (self.augment_args)(Command::new(self.name))
+            .about(self.description)
+            .visible_aliases(self.aliases);
+        self.defaults.iter().fold(command, |command, (id, values)| {
+            command.mut_arg(id, |arg| arg.default_values(values))
+        })
+    }
+
+    /// Parses the arguments of the suite, the returned future runs it
+    pub fn start(
+        &self,
+        matches: &ArgMatches,
+        ctx: SuiteContext,
+    ) -> Result<BoxFuture<'static, Result<()>>> {
+        (self.run)(matches, ctx)
+    }
+}
+
+/// All the suites that can be run by the harness
+pub fn registry() -> Vec<SuiteEntry> {
+    vec![
+        SuiteEntry::of::<ChangeTrackingSuite>(),
+        SuiteEntry::of::<ExplicitTxnSuite>(),
+        SuiteEntry::of::<MultiTableInsertSuite>(),
+        SuiteEntry::of::<AutoVacuumSuite>(),
+        SuiteEntry::of::<Vacuum2Suite>(),
+        SuiteEntry::of::<VacuumSuite>(),
+        SuiteEntry::of::<MergeIntoSuite>(),
+        SuiteEntry::of::<ReplaceIntoSuite>(),
+        SuiteEntry::of::<TaskSuite>(),
+    ]
+}
+
+/// Runs the phases of a suite created with `ctx`, the teardown drops the databases it created
+pub async fn run_suite<S: Suite>(suite: S, ctx: &SuiteContext) -> Result<()> {
+    info!(
+        "===== [{}] {} (run {}) =====",
+        S::NAME,
+        S::DESCRIPTION,
+        ctx.run_id()
+    );
+    let start = Instant::now();
+
+    let result = run_phases(&suite, ctx).await;
+    // before the teardown drops the tables to inspect
+    if let (Err(e), Some(dir)) = (&result, &ctx.forensics_dir) {
+        forensics::collect_on_failure(ctx, dir, S::NAME, e).await;
+    }
+    let result = if result.is_err() && ctx.keep_on_failure {
+        warn!(
+            "===== [{}] teardown skipped, keeping databases {} =====",
+            S::NAME,
+            ctx.databases().join(", ")
+        );
+        result
+    } else {
+        let teardown = async {
+            let teardown = suite.teardown().await;
+            teardown.and(ctx.drop_databases().await)
+        };
+        result.and(run_phase(S::NAME, "teardown", teardown).await)
+    };
+
+    match &result {
+        Ok(_) => info!("===== [{}] PASSED in {:?} =====", S::NAME, start.elapsed()),
+        Err(e) => error!(
+            "===== [{}] FAILED in {:?}: {:#} =====",
+            S::NAME,
+            start.elapsed(),
+            e
+        ),
+    }
+    result
+}
+
+async fn run_phases<S: Suite>(suite: &S, ctx: &SuiteContext) -> Result<()> {
+    let shutdown = &ctx.shutdown;
+    run_phase(S::NAME, "setup", suite.setup()).await?;
+    let execute = {
+        // the setup and the verification see a healthy network
+        let _faults: Vec<_> = ctx
+            .fault_proxies
+            .iter()
+            .map(|proxy| proxy.inject())
+            .collect();
+        let workload = async {
+            let start = Instant::now();
+            let execute = shutdown
+                .grace(run_phase(S::NAME, "execute", suite.execute()))
+                .await;
+            ctx.stats.record_execution(start.elapsed());
+            execute
+        };
+        match &ctx.crasher {
+            Some(crasher) if S::CRASH_RECOVERY => crasher.execute(suite, workload).await?,
+            Some(_) => {
+                warn!(
+                    "===== [{}] does not support crash recovery, executing without crashes =====",
+                    S::NAME
+                );
+                workload.await
+            }
+            None => workload.await,
+        }
+    };
+    match execute {
+        Some(result) => result?,
+        None => warn!(
+            "===== [{}] execute did not stop within the grace period, abandoned =====",
+            S::NAME
+        ),
+    }
+    if shutdown.is_requested() {
+        warn!(
+            "===== [{}] interrupted, verifying the partial state =====",
+            S::NAME
+        );
+    }
+    run_phase(S::NAME, "verify", suite.verify()).await
+}
+
+async fn run_phase(suite: &str, phase: &str, fut: impl Future<Output = Result<()>>) -> Result<()> {
+    info!("===== [{suite}] {phase} =====");
+    let start = Instant::now();
+    let result = fut
+        .await
+        .with_context(|| format!("{phase} of {suite} failed"));
+    info!(
+        "===== [{suite}] {phase} done in {:?} =====",
+        start.elapsed()
+    );
+    result
+}
@@ -0,0 +1,164 @@
+use crate::preflight::{Requirement, CHANGE_TRACKING};
+use crate::suite::{Suite, SuiteContext};
+use crate::util::ConnectionExt;
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Parser;
+use log::info;
+
+const SET_UP: &str = "./sql/task/set_up.sql";
+const MERGE: &str = "./sql/task/merge.sql";
+const TXN_MERGE: &str = "./sql/task/txn_merge.sql";
+const TASK_TXN_MERGE: &str = "./sql/task/task_txn_merge.sql";
+
+/// Task Testing Script
+/// - Consumes a stream by merging into 4 tables: plainly, within an explicit transaction
+///   and within an explicit transaction executed by a task.
+/// - Requires stage `s_temp` loaded with the csv files of `json_table`,
+///   and the warehouse `task-test` to execute the task.
+#[derive(Parser, Clone, Debug)]
+pub struct Args {}
+
+pub struct TaskSuite {
+    ctx: SuiteContext,
+    database: String,
+}
+
+#[async_trait]
+impl Suite for TaskSuite {
+    type Args = Args;
+    const NAME: &'static str = "task";
+    const DESCRIPTION: &'static str =
+        "Stream consumption by merge, merge in explicit transaction and task";
+    const REQUIREMENTS: &'static [Requirement] = &[
+        Requirement::Feature(&CHANGE_TRACKING),
+        Requirement::Function("stream_status"),
+    ];
+
+    fn new(_args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("task");
+        Self { ctx, database }
+    }
+
+    // each scenario sets up its own tables, in the database of the run
+    async fn setup(&self) -> Result<()> {
+        let c = self.ctx.new_connection().await?;
+        c.exec(&format!("create or replace database {}", self.database))
+            .await?;
+        Ok(())
+    }
+
+    async fn execute(&self) -> Result<()> {
+        run(&self.ctx, &self.database).await
+    }
+
+    // the expectations are asserted along the execution
+    async fn verify(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // the task is not dropped with the database
+    async fn teardown(&self) -> Result<()> {
+        let c = self.ctx.new_connection().await?;
+        c.exec(&format!(
+            "drop task if exists {}_merge_task;",
+            self.database
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+async fn run(ctx: &SuiteContext, db: &str) -> Result<()> {
+    {
+        set_up(ctx, db).await?;
+        let c = ctx.new_connection().await?;
+        c.exec_lines_in(MERGE, db).await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table;"),
+            vec![(10000,)],
+        )
+        .await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table_stream;"),
+            vec![(10000,)],
+        )
+        .await?;
+        info!("merge result:");
+        print_result(ctx, db).await?;
+    }
+    // the remaining scenarios are skipped once a shutdown is requested
+    if ctx.shutdown().is_requested() {
+        return Ok(());
+    }
+    {
+        set_up(ctx, db).await?;
+        let c = ctx.new_connection().await?;
+        c.exec_lines_in(TXN_MERGE, db).await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table;"),
+            vec![(10000,)],
+        )
+        .await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table_stream;"),
+            vec![(0,)],
+        )
+        .await?;
+        info!("merge + txn result:");
+        print_result(ctx, db).await?;
+    }
+    if ctx.shutdown().is_requested() {
+        return Ok(());
+    }
+    {
+        set_up(ctx, db).await?;
+        let c = ctx.new_connection().await?;
+        // tasks are not scoped by database, the task of the run is named after its database
+        c.exec(&format!("drop task if exists {db}_merge_task;"))
+            .await?;
+        c.exec_lines_in(TASK_TXN_MERGE, db).await?;
+        c.exec(&format!("execute task {db}_merge_task")).await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table;"),
+            vec![(10000,)],
+        )
+        .await?;
+        c.assert_query(
+            &format!("select count(*) from {db}.json_table_stream;"),
+            vec![(0,)],
+        )
+        .await?;
+        info!("merge + txn + task result:");
+        print_result(ctx, db).await?;
+    }
+
+    Ok(())
+}
+
+async fn set_up(ctx: &SuiteContext, db: &str) -> Result<()> {
+    let c = ctx.new_connection().await?;
+    c.exec_lines_in(SET_UP, db).await?;
+    c.assert_query(
+        &format!("select count(*) from {db}.json_table;"),
+        vec![(10000,)],
+    )
+    .await?;
+    c.assert_query(
+        &format!("select count(*) from {db}.json_table_stream;"),
+        vec![(10000,)],
+    )
+    .await?;
+    Ok(())
+}
+
+async fn print_result(ctx: &SuiteContext, db: &str) -> Result<()> {
+    let c = ctx.new_connection().await?;
+    for i in 1..=4 {
+        let count: Vec<(u64,)> = c
+            .exec_query(&format!("select count(*) from {db}.json_table_flag_{i};"))
+            .await?;
+        info!("json_table_flag_{i}: {:?}", count);
+    }
+    Ok(())
+}
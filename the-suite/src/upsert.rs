@@ -0,0 +1,574 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use databend_driver::Connection;
+use futures_util::StreamExt;
+use log::info;
+
+use crate::datagen::{values_source, ORDER_COLUMNS};
+use crate::errors::{ErrorCategory, OpError};
+use crate::nodes::Role;
+use crate::suite::{Suite, SuiteContext};
+use crate::util::{read_statements, ConnectionExt};
+
+const SET_UP: &str = "./sql/upsert/setup.sql";
+
+/// Merge-into / Replace-into Testing Script
+/// - Upserts batches of correlated rows into `test_order`, while the table is being compacted,
+///   purged and re-clustered concurrently
+/// - See docs/upsert.md for the details of the scenario
+#[derive(Parser, Clone, Debug)]
+pub struct Args {
+    /// Number of upsert iterations, each iteration upserts 1000 rows
+    #[arg(long, default_value_t = 1000)]
+    iterations: u32,
+}
+
+/// The statement used to upsert rows into `test_order`
+pub trait Upsert: Send + Sync + 'static {
+    const NAME: &'static str;
+    const DESCRIPTION: &'static str;
+    /// Name of the database of the runs, see [`SuiteContext::database`]
+    const DATABASE: &'static str;
+    const METRICS_PATTERN: &'static str;
+    /// Kind of the upsert operations in the stats
+    const OP_KIND: &'static str;
+
+    /// upsert `source` (a sub query of the same schema of `test_order`) into `test_order`,
+    /// rows are matched on (id, insert_time)
+    fn upsert_sql(source: &str) -> String;
+}
+
+pub struct MergeInto;
+
+impl Upsert for MergeInto {
+    const NAME: &'static str = "merge-into";
+    const DESCRIPTION: &'static str =
+        "Concurrent `merge into` and table maintenance on `test_order`";
+    const DATABASE: &'static str = "test_merge_into";
+    const METRICS_PATTERN: &'static str = "%merge%";
+    const OP_KIND: &'static str = "merge";
+
+    fn upsert_sql(source: &str) -> String {
+        format!(
+            "merge into test_order as t
+              using ({source}) as s
+              on t.id = s.id and t.insert_time = s.insert_time
+              when matched then update *
+              when not matched then insert *"
+        )
+    }
+}
+
+pub struct ReplaceInto;
+
+impl Upsert for ReplaceInto {
+    const NAME: &'static str = "replace-into";
+    const DESCRIPTION: &'static str =
+        "Concurrent `replace into` and table maintenance on `test_order`";
+    const DATABASE: &'static str = "test_replace_into";
+    const METRICS_PATTERN: &'static str = "%replace%";
+    const OP_KIND: &'static str = "replace";
+
+    fn upsert_sql(source: &str) -> String {
+        format!("replace into test_order on(id, insert_time) ({source})")
+    }
+}
+
+pub type MergeIntoSuite = UpsertSuite<MergeInto>;
+pub type ReplaceIntoSuite = UpsertSuite<ReplaceInto>;
+
+pub struct UpsertSuite<U> {
+    args: Args,
+    ctx: SuiteContext,
+    database: String,
+    success_upsert_stmts: Arc<AtomicU32>,
+    /// Upserts failed on the client which the server may have committed, e.g. on a lost connection
+    ambiguous_upsert_stmts: Arc<AtomicU32>,
+    _upsert: PhantomData<fn() -> U>,
+}
+
+impl<U> Clone for UpsertSuite<U> {
+    fn clone(&self) -> Self {
+        Self {
+            args: self.args.clone(),
+            ctx: self.ctx.clone(),
+            database: self.database.clone(),
+            success_upsert_stmts: self.success_upsert_stmts.clone(),
+            ambiguous_upsert_stmts: self.ambiguous_upsert_stmts.clone(),
+            _upsert: PhantomData,
+        }
+    }
+}
+
+impl<U: Upsert> UpsertSuite<U> {
+    async fn new_connection(&self) -> Result<Box<dyn Connection>> {
+        let conn = self.ctx.new_connection().await?;
+        conn.exec(&format!("use {}", self.database)).await?;
+        Ok(conn)
+    }
+
+    /// A connection of a worker of `role`, see [`SuiteContext::new_connection_as`]
+    async fn new_connection_as(&self, role: Role) -> Result<Box<dyn Connection>> {
+        let conn = self.ctx.new_connection_as(role).await?;
+        conn.exec(&format!("use {}", self.database)).await?;
+        Ok(conn)
+    }
+
+    async fn run_setup_script(&self) -> Result<()> {
+        info!("=====running setup script====");
+
+        let conn = self.ctx.new_connection().await?;
+        let setup_statements = read_statements(SET_UP)?;
+
+        let database = &self.database;
+        let db_set_sqls = [
+            format!("create or replace database {database}"),
+            format!("use {database}"),
+        ];
+
+        for sql in db_set_sqls {
+            info!("executing sql: {}", sql);
+            conn.exec(&sql).await?;
+        }
+
+        for statement in &setup_statements {
+            info!("executing sql: {}", statement.sql);
+            conn.exec_statement(SET_UP, statement).await?;
+        }
+
+        info!("====setup done====");
+
+        Ok(())
+    }
+
+    async fn run_upserts(&self) -> Result<u32> {
+        info!("=====running test script ====");
+
+        let upsert_handle = tokio::spawn({
+            let suite = self.clone();
+            async move {
+                let mut num_of_success = 0;
+                let shutdown = suite.ctx.shutdown();
+                for batch_id in 0..suite.args.iterations {
+                    if shutdown.is_requested() {
+                        info!("shutdown requested, stopping after {batch_id} batches");
+                        break;
+                    }
+                    info!("executing batch: {}", batch_id);
+                    let success = suite.exec_upsert(batch_id).await?;
+                    if success {
+                        num_of_success += 1;
+                    }
+
+                    if (batch_id + 1).is_multiple_of(7) {
+                        // introduce more conflicts if possible
+                        let ids = vec![batch_id, batch_id / 2, batch_id / 3];
+                        suite.exec_upsert_conflict(&ids).await?;
+                    }
+                }
+                Ok::<_, anyhow::Error>(num_of_success)
+            }
+        });
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        // background tasks to maintain the table
+        let maintain_handle = tokio::spawn({
+            let suite = self.clone();
+            let stop_flag = stop_flag.clone();
+            let shutdown = self.ctx.shutdown();
+            async move {
+                let mut batch_id = 0;
+                // the stop flag is not set if the execution is dropped at the end of a grace period
+                while !stop_flag.load(Ordering::Relaxed) && !shutdown.is_requested() {
+                    // we do not care if this fails
+                    let _ = suite.exec_table_maintenance(batch_id).await;
+                    batch_id += 1;
+                }
+                Ok::<_, anyhow::Error>(())
+            }
+        });
+
+        // wait for upsert stmts to finish
+        let success_upsert_stmts = upsert_handle.await??;
+
+        // then we shutdown the table maintenance tasks
+        stop_flag.store(true, Ordering::Relaxed);
+
+        maintain_handle.await??;
+
+        Ok(success_upsert_stmts)
+    }
+
+    async fn exec_upsert_conflict(&self, batch_ids: &[u32]) -> Result<bool> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+        let name = U::NAME;
+        let ids = batch_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        info!("executing {name} (with conflict) : [{}]", ids);
+
+        // generate sub query which combine all the data that generated by history batch ids
+        let filter = batch_ids
+            .iter()
+            .map(|id| format!("id1 = {}", id))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        let sub_query = format!("select * from test_order where {filter}");
+
+        // upsert these history data into the table (itself). while table being compacted and re-clustered
+        // this may lead to partial and total block update.
+        let sql = U::upsert_sql(&sub_query);
+
+        let result = conn
+            .exec_retry(
+                &self.ctx.retry_policy(),
+                &self.ctx.stats(),
+                U::OP_KIND,
+                &sql,
+            )
+            .await;
+        match result {
+            Ok(_) => {
+                info!("Ok. {name} batch (with conflict) : [{}]", ids);
+                Ok(true)
+            }
+            Err(e) => {
+                // upsert may be failed due to concurrent mutations (compact, purge, recluster)
+                info!("Err. {name} batch (with conflict) : [{}]. {e}", ids);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn exec_upsert(&self, batch_id: u32) -> Result<bool> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+        let name = U::NAME;
+
+        info!("executing {name} batch : {}", batch_id);
+        let batch_correlated_value = batch_id * 7;
+        let rows = self.ctx.datagen(batch_id as u64).order_rows(
+            1000,
+            batch_id as u64,
+            batch_correlated_value as u64,
+        );
+        let source = values_source(&rows, ORDER_COLUMNS);
+        let sql = U::upsert_sql(&source);
+        let result = conn
+            .exec_retry(
+                &self.ctx.retry_policy(),
+                &self.ctx.stats(),
+                U::OP_KIND,
+                &sql,
+            )
+            .await;
+        match result {
+            Ok(_) => {
+                info!("Ok. {name} batch : {}", batch_id);
+                Ok(true)
+            }
+            Err(e) => {
+                // upsert may be failed due to concurrent mutations (compact, purge, recluster),
+                // the server may still have committed it if the connection was lost or timed out
+                info!("Err. {name} batch : {}. {e}", batch_id);
+                if matches!(
+                    e.category(),
+                    ErrorCategory::Connection | ErrorCategory::Timeout
+                ) {
+                    self.ambiguous_upsert_stmts.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    async fn exec_table_maintenance(&self, batch_id: i32) -> Result<()> {
+        info!("executing table maintenance batch : {}", batch_id);
+        let conn = self.new_connection_as(Role::Maintenance).await?;
+        let sqls = [
+            (
+                "compact_segment",
+                "optimize table test_order compact segment",
+            ),
+            ("compact", "optimize table test_order compact"),
+            ("purge", "optimize table test_order purge"),
+            ("recluster", "alter table test_order recluster"),
+        ];
+        for (kind, sql) in sqls {
+            let result = self.ctx.stats().timed(kind, conn.exec(sql)).await;
+            match result {
+                Ok(_) => {
+                    info!("Ok. maintenance batch : {}", batch_id);
+                }
+                Err(e) => {
+                    info!("Err. maintenance batch : {}. {e}", batch_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_table_state(&self, success_upsert_stmts: u32) -> Result<()> {
+        info!("==========================");
+        info!("====verify table state====");
+        info!("==========================");
+        let conn = self.new_connection().await?;
+        let name = U::NAME;
+
+        info!(
+            "number of successfully executed {name} statements : {}",
+            success_upsert_stmts
+        );
+
+        // - check the table data match the number of successfully executed upsert statements
+        {
+            info!("CHECK: value of successfully executed {name} statements");
+
+            // For most of the cases, there should be 1000 * success_upsert_stmts rows
+            //
+            // but in a client/server setting, one can not assume that the client always agree with the server
+            // if a statement is successfully executed on the server (e.g. communication failure).
+            //
+            // thus we just show the number, instead of asserting they are equal. The statements committed
+            // despite failing on the client are listed as ambiguous commits in the report of the run.
+            let count = conn.query_count("select count() from test_order").await?;
+            info!(
+                "CHECK: value of successfully executed {name} statements: client {}, server {}",
+                success_upsert_stmts as u64 * 1000,
+                count
+            );
+
+            // for each unique id1, the count should be 1000 (even if there are communication failures)
+            let count = conn
+                .query_count(
+                    "select count() from
+                        (select count() a, id1 from test_order group by id1)
+                        where a != 1000",
+                )
+                .await?;
+            if count != 0 {
+                return Err(anyhow!(
+                    "{count} batches of {name} do not have exactly 1000 rows"
+                ));
+            }
+
+            // every batch upserts rows of its own id2, committed by the successful statements and
+            // possibly by the ambiguous ones
+            let ambiguous = self.ambiguous_upsert_stmts.load(Ordering::Relaxed);
+            let count = conn
+                .query_count("select count(distinct(id2)) from test_order")
+                .await?;
+            info!(
+                "CHECK: distinct ids: client {} ({} ambiguous), server {}",
+                success_upsert_stmts, ambiguous, count
+            );
+            let committed = success_upsert_stmts as u64..=(success_upsert_stmts + ambiguous) as u64;
+            if !committed.contains(&count) {
+                return Err(anyhow!(
+                    "distinct ids mismatch: client {} ({} ambiguous), server {}",
+                    success_upsert_stmts,
+                    ambiguous,
+                    count
+                ));
+            }
+        }
+
+        // - check the value of correlated column
+        // for all the rows, id2 should be equal to id1 * 7
+        {
+            info!("CHECK: value of correlated column");
+            let count = conn
+                .query_count("select count() from test_order where id2 != id1 * 7")
+                .await?;
+            if count != 0 {
+                return Err(anyhow!("{count} rows with id2 != id1 * 7"));
+            }
+        }
+
+        // - full table scan, ensure that the table data is not damaged
+        info!("CHECK: full table scanning");
+        {
+            let mut rows = conn
+                .query_iter("select * from test_order ignore_result")
+                .await
+                .map_err(|e| anyhow!("full table scan failed: {e}"))?;
+            while rows.next().await.is_some() {}
+        }
+
+        info!("===========================");
+        info!("======     PASSED      ====");
+        info!("===========================");
+
+        info!("========METRICS============");
+        let sql = format!(
+            "select metric, value from system.metrics where metric like '{}' or metric like '%conflict%' order by metric",
+            U::METRICS_PATTERN
+        );
+        let mut rows = conn.query_iter(&sql).await?;
+        while let Some(r) = rows.next().await {
+            let (metric, value): (String, String) = r?
+                .try_into()
+                .map_err(|e| anyhow!("unexpected metric: {e:?}"))?;
+            info!("{metric} : {value}");
+        }
+        info!("===========================");
+
+        info!("======CLUSTERING INFO======");
+        let sql = format!(
+            "select cluster_key, block_count, constant_block_count, unclustered_block_count, \
+             average_overlaps, average_depth, block_depth_histogram \
+             from clustering_information('{}', 'test_order')",
+            self.database
+        );
+        let mut rows = conn.query_iter(&sql).await?;
+        while let Some(r) = rows.next().await {
+            let (
+                cluster_key,
+                block_count,
+                constant_block_count,
+                unclustered_block_count,
+                average_overlaps,
+                average_depth,
+                block_depth_histogram,
+            ): (String, u64, u64, u64, f64, f64, String) = r?
+                .try_into()
+                .map_err(|e| anyhow!("unexpected clustering information: {e:?}"))?;
+            info!("cluster_key : {cluster_key}");
+            info!("block_count: {block_count}");
+            info!("constant_block_count: {constant_block_count}");
+            info!("unclustered_block_count: {unclustered_block_count}");
+            info!("average_overlaps: {average_overlaps}");
+            info!("average_depth: {average_depth}");
+            info!("block_depth_histogram: {block_depth_histogram}");
+        }
+        info!("===========================");
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<U: Upsert> Suite for UpsertSuite<U> {
+    type Args = Args;
+    const NAME: &'static str = U::NAME;
+    const DESCRIPTION: &'static str = U::DESCRIPTION;
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database(U::DATABASE);
+        Self {
+            args,
+            ctx,
+            database,
+            success_upsert_stmts: Arc::new(AtomicU32::new(0)),
+            ambiguous_upsert_stmts: Arc::new(AtomicU32::new(0)),
+            _upsert: PhantomData,
+        }
+    }
+
+    async fn setup(&self) -> Result<()> {
+        self.run_setup_script().await
+    }
+
+    async fn execute(&self) -> Result<()> {
+        let success = self.run_upserts().await?;
+        self.success_upsert_stmts.store(success, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<()> {
+        self.check_table_state(self.success_upsert_stmts.load(Ordering::Relaxed))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{uint, MockServer, Reply};
+    use crate::suite::{run_suite, SuiteEntry};
+
+    fn args() -> Args {
+        Args::try_parse_from(["merge-into", "--iterations", "4"]).unwrap()
+    }
+
+    /// The server has the rows of `committed` batches
+    fn server(mock: &MockServer, committed: u64) {
+        mock.when(
+            "count(distinct(id2))",
+            Reply::rows(vec![vec![uint(committed)]]),
+        );
+        mock.when("select count()", Reply::rows(vec![vec![uint(0)]]));
+    }
+
+    /// Runs the registered suite, a conflict then the reply `failure` to the upserts, allowing
+    /// the errors of `allowed`
+    async fn run_entry(failure: Reply, allowed: ErrorCategory, committed: u64) -> Result<()> {
+        let entry = SuiteEntry::of::<MergeIntoSuite>();
+        let matches = entry
+            .command()
+            .try_get_matches_from(["merge-into", "--iterations", "4"])
+            .unwrap();
+        let mock = MockServer::new();
+        mock.when(
+            "merge into test_order",
+            Reply::error(4001, "conflict").times(1),
+        );
+        mock.when("merge into test_order", failure.times(1));
+        server(&mock, committed);
+        let ctx = mock
+            .context()
+            .with_allowed_errors(vec![ErrorCategory::Conflict, allowed]);
+        entry.start(&matches, ctx)?.await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ambiguous_upserts_may_have_committed() {
+        // 2 successful batches, the lost connection may or may not have committed the third
+        for committed in [2, 3] {
+            let lost = Reply::transport("connection reset");
+            run_entry(lost, ErrorCategory::Connection, committed)
+                .await
+                .unwrap();
+        }
+        let timeout = Reply::error(4003, "transaction timeout");
+        run_entry(timeout, ErrorCategory::Timeout, 3).await.unwrap();
+
+        let lost = Reply::transport("connection reset");
+        let error = run_entry(lost, ErrorCategory::Connection, 4)
+            .await
+            .unwrap_err();
+        let expected = "distinct ids mismatch: client 2 (1 ambiguous), server 4";
+        assert!(format!("{error:#}").contains(expected), "{error:#}");
+
+        // the other failures did not commit
+        let panicked = Reply::error(1001, "panicked");
+        let error = run_entry(panicked, ErrorCategory::Unknown, 3)
+            .await
+            .unwrap_err();
+        let expected = "distinct ids mismatch: client 2 (0 ambiguous), server 3";
+        assert!(format!("{error:#}").contains(expected), "{error:#}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn missing_batch_fails_the_suite() {
+        let mock = MockServer::new();
+        mock.when(
+            "merge into test_order",
+            Reply::error(4001, "conflict").times(1),
+        );
+        server(&mock, 2);
+        let ctx = mock.context();
+        let result = run_suite(MergeIntoSuite::new(args(), ctx.clone()), &ctx).await;
+
+        let error = format!("{:#}", result.unwrap_err());
+        let expected = "distinct ids mismatch: client 3 (0 ambiguous), server 2";
+        assert!(error.contains(expected), "{error}");
+    }
+}
@@ -1,13 +1,226 @@
 use std::{fmt::Debug, vec};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use databend_driver::Connection;
 use futures_util::StreamExt;
 use log::error;
 
+use crate::retry::RetryPolicy;
+use crate::stats::Stats;
+
+/// A statement of a SQL script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub sql: String,
+    /// Line of the script the statement starts at, 1-based
+    pub line: usize,
+}
+
+/// Placeholder of SQL scripts for the database of the run, see [`read_statements_in`]
+pub const DATABASE_PLACEHOLDER: &str = "${DATABASE}";
+
+/// Reads a SQL script and splits it into statements, see [`split_statements`]
+pub fn read_statements(path: &str) -> Result<Vec<Statement>> {
+    let script = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read sql script {path}"))?;
+    split_statements(&script).with_context(|| format!("failed to split sql script {path}"))
+}
+
+/// Like [`read_statements`], with the `${DATABASE}` of the script replaced by `database`
+pub fn read_statements_in(path: &str, database: &str) -> Result<Vec<Statement>> {
+    let statements = read_statements(path)?;
+    Ok(statements
+        .into_iter()
+        .map(|s| Statement {
+            sql: s.sql.replace(DATABASE_PLACEHOLDER, database),
+            ..s
+        })
+        .collect())
+}
+
+/// Splits a SQL script into statements on `;`, except for the `;` inside of
+/// - quoted strings and identifiers: `'..'`, `".."`, `` `..` ``
+/// - line comments `-- ..` and block comments `/* .. */`
+/// - `$$ .. $$` bodies
+/// - scripting blocks `BEGIN .. END` and `CASE .. END`, which may be nested
+///
+/// `BEGIN` followed by `;`, `TRANSACTION` or `WORK` starts a transaction rather than a block,
+/// and `END IF`, `END FOR`, `END LOOP`, `END WHILE` and `END REPEAT` close no block.
+/// Statements consisting of comments only are dropped, an `END` closing no block is an error.
+pub fn split_statements(script: &str) -> Result<Vec<Statement>> {
+    let bytes = script.as_bytes();
+    let mut statements = vec![];
+    // byte offset and line of the first token of the current statement
+    let mut start: Option<(usize, usize)> = None;
+    // byte offset right after the last token of the current statement
+    let mut end = 0;
+    // lines of the open blocks, the innermost last
+    let mut blocks: Vec<usize> = vec![];
+    let mut line = 1;
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let token_line = line;
+        let token_start = pos;
+        match bytes[pos] {
+            b'\n' => {
+                line += 1;
+                pos += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            b'-' if bytes.get(pos + 1) == Some(&b'-') => {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(pos + 1) == Some(&b'*') => {
+                pos = skip_until(bytes, pos + 2, b"*/", &mut line)
+                    .ok_or_else(|| anyhow!("unterminated block comment at line {token_line}"))?;
+                continue;
+            }
+            b';' if blocks.is_empty() => {
+                if let Some((offset, line)) = start.take() {
+                    statements.push(Statement {
+                        sql: script[offset..end].to_owned(),
+                        line,
+                    });
+                }
+                pos += 1;
+                continue;
+            }
+            quote @ (b'\'' | b'"' | b'`') => {
+                pos = skip_quoted(bytes, pos + 1, quote, &mut line)
+                    .ok_or_else(|| anyhow!("unterminated quote at line {token_line}"))?;
+            }
+            b'$' if bytes.get(pos + 1) == Some(&b'$') => {
+                pos = skip_until(bytes, pos + 2, b"$$", &mut line)
+                    .ok_or_else(|| anyhow!("unterminated $$ body at line {token_line}"))?;
+            }
+            c if is_ident(c) => {
+                while pos < bytes.len() && is_ident(bytes[pos]) {
+                    pos += 1;
+                }
+                let word = &script[token_start..pos];
+                if word.eq_ignore_ascii_case("CASE") {
+                    blocks.push(token_line);
+                } else if word.eq_ignore_ascii_case("BEGIN") {
+                    let next = next_word(script, pos);
+                    if !["", ";", "TRANSACTION", "WORK"]
+                        .iter()
+                        .any(|w| next.eq_ignore_ascii_case(w))
+                    {
+                        blocks.push(token_line);
+                    }
+                } else if word.eq_ignore_ascii_case("END") {
+                    let next = next_word(script, pos);
+                    if !["IF", "FOR", "LOOP", "WHILE", "REPEAT"]
+                        .iter()
+                        .any(|w| next.eq_ignore_ascii_case(w))
+                        && blocks.pop().is_none()
+                    {
+                        return Err(anyhow!("END without BEGIN or CASE at line {token_line}"));
+                    }
+                }
+            }
+            _ => pos += 1,
+        }
+        start.get_or_insert((token_start, token_line));
+        end = pos;
+    }
+
+    if let Some(line) = blocks.pop() {
+        return Err(anyhow!("unterminated BEGIN or CASE block at line {line}"));
+    }
+    if let Some((offset, line)) = start {
+        statements.push(Statement {
+            sql: script[offset..end].to_owned(),
+            line,
+        });
+    }
+    Ok(statements)
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Returns the position right after `end`, counting the lines skipped
+fn skip_until(bytes: &[u8], mut pos: usize, end: &[u8], line: &mut usize) -> Option<usize> {
+    while pos < bytes.len() {
+        if bytes[pos..].starts_with(end) {
+            return Some(pos + end.len());
+        }
+        if bytes[pos] == b'\n' {
+            *line += 1;
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// Returns the position right after the closing quote, a quote is escaped by doubling it
+/// or, in strings, by a backslash
+fn skip_quoted(bytes: &[u8], mut pos: usize, quote: u8, line: &mut usize) -> Option<usize> {
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' if quote == b'\'' => pos += 1,
+            b'\n' => *line += 1,
+            c if c == quote => {
+                if bytes.get(pos + 1) == Some(&quote) {
+                    pos += 1;
+                } else {
+                    return Some(pos + 1);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    None
+}
+
+/// The word or the punctuation following `pos`, skipping whitespaces and comments,
+/// empty at the end of the script
+fn next_word(script: &str, mut pos: usize) -> &str {
+    let bytes = script.as_bytes();
+    // lines are counted by the caller
+    let mut lines = 0;
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if bytes[pos..].starts_with(b"--") {
+            while pos < bytes.len() && bytes[pos] != b'\n' {
+                pos += 1;
+            }
+        } else if bytes[pos..].starts_with(b"/*") {
+            match skip_until(bytes, pos + 2, b"*/", &mut lines) {
+                Some(end) => pos = end,
+                None => return "",
+            }
+        } else {
+            break;
+        }
+    }
+    let start = pos;
+    while pos < bytes.len() && is_ident(bytes[pos]) {
+        pos += 1;
+    }
+    if pos == start {
+        pos += script[start..].chars().next().map_or(0, char::len_utf8);
+    }
+    &script[start..pos]
+}
+
 #[allow(dead_code)]
 pub trait ConnectionExt: Connection {
-    async fn assert_query<T>(&self, sql: &str, expected: Vec<T>)
+    /// Fails unless the rows of the result are `expected`
+    async fn assert_query<T>(&self, sql: &str, expected: Vec<T>) -> Result<()>
     where
         T: TryFrom<databend_driver::Row> + 'static + Debug + PartialEq,
         <T as TryFrom<databend_driver::Row>>::Error: Debug,
@@ -17,7 +230,13 @@ pub trait ConnectionExt: Connection {
         if let Err(e) = &result {
             error!("execute sql [{}], failed. {}", sql, e)
         }
-        assert_eq!(result.unwrap(), expected);
+        let actual = result?;
+        if actual != expected {
+            return Err(anyhow!(
+                "unexpected result of [{sql}]: {actual:?}, expected {expected:?}"
+            ));
+        }
+        Ok(())
     }
 
     async fn exec_query<T>(&self, sql: &str) -> Result<Vec<T>>
@@ -28,24 +247,89 @@ pub trait ConnectionExt: Connection {
         let mut rows = self.query_iter(sql).await?;
         let mut res = vec![];
         while let Some(r) = rows.next().await {
-            let row: T = r.unwrap().try_into().unwrap();
+            let row: T = r?
+                .try_into()
+                .map_err(|e| anyhow!("unexpected row of [{sql}]: {e:?}"))?;
             res.push(row);
         }
         Ok(res)
     }
 
+    /// The count of a `SELECT count() ...`
+    async fn query_count(&self, sql: &str) -> Result<u64> {
+        let row = self
+            .query_row(sql)
+            .await?
+            .ok_or_else(|| anyhow!("no row returned by [{sql}]"))?;
+        let (count,): (u64,) = row
+            .try_into()
+            .map_err(|e| anyhow!("unexpected row of [{sql}]: {e:?}"))?;
+        Ok(count)
+    }
+
+    /// Rows of the result, with the values formatted as strings
+    async fn query_strings(&self, sql: &str) -> Result<Vec<Vec<String>>> {
+        let rows = self.query_all(sql).await?;
+        Ok(rows
+            .iter()
+            .map(|row| row.values().iter().map(|v| v.to_string()).collect())
+            .collect())
+    }
+
+    /// Executes the statements of a SQL script one by one, see [`split_statements`]
     async fn exec_lines(&self, path: &str) -> Result<()> {
-        let sql = std::fs::read_to_string(path)?;
-        let sqls: Vec<&str> = sql.split(";").collect();
-        for sql in sqls {
-            if sql.trim().is_empty() {
-                continue;
-            }
-            self.exec(sql).await?;
+        for statement in read_statements(path)? {
+            self.exec_statement(path, &statement).await?;
+        }
+        Ok(())
+    }
+
+    /// Executes the statements of a SQL script referring to `database` as `${DATABASE}`
+    async fn exec_lines_in(&self, path: &str, database: &str) -> Result<()> {
+        for statement in read_statements_in(path, database)? {
+            self.exec_statement(path, &statement).await?;
         }
         Ok(())
     }
 
+    /// Executes a statement of the script at `path`, the error tells where the statement is
+    async fn exec_statement(&self, path: &str, statement: &Statement) -> Result<()> {
+        self.exec(&statement.sql)
+            .await
+            .with_context(|| format!("failed to execute statement at {path}:{}", statement.line))?;
+        Ok(())
+    }
+
+    /// Executes a statement of a workload under `policy`, every attempt is counted as `kind`
+    async fn exec_retry(
+        &self,
+        policy: &RetryPolicy,
+        stats: &Stats,
+        kind: &str,
+        sql: &str,
+    ) -> databend_driver::Result<i64> {
+        policy.run(stats, kind, || self.exec(sql)).await
+    }
+
+    /// Executes the statements in an explicit transaction, rolled back if one of them fails,
+    /// so that it can be retried as a whole from its `BEGIN`
+    async fn exec_txn(&self, statements: &[String]) -> databend_driver::Result<()> {
+        let result = async {
+            self.exec("BEGIN").await?;
+            for sql in statements {
+                self.exec(sql).await?;
+            }
+            self.exec("COMMIT").await
+        }
+        .await;
+        if result.is_err() {
+            if let Err(e) = self.exec("ROLLBACK").await {
+                error!("failed to roll back the transaction: {e}");
+            }
+        }
+        result.map(|_| ())
+    }
+
     async fn begin(&self) -> Result<()> {
         self.exec("BEGIN").await?;
         Ok(())
@@ -62,4 +346,149 @@ pub trait ConnectionExt: Connection {
     }
 }
 
-impl ConnectionExt for dyn Connection {}
+impl<T: Connection + ?Sized> ConnectionExt for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts the lines and the SQL of the statements of `script`
+    fn assert_split(script: &str, expected: &[(usize, &str)]) {
+        let statements = split_statements(script).unwrap();
+        let statements: Vec<_> = statements
+            .iter()
+            .map(|s| (s.line, s.sql.as_str()))
+            .collect();
+        assert_eq!(statements, expected);
+    }
+
+    fn error(script: &str) -> String {
+        split_statements(script).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_split(
+            "select 1;\nselect 2;\n\n  select 3",
+            &[(1, "select 1"), (2, "select 2"), (4, "select 3")],
+        );
+        assert_split(";;\n;", &[]);
+    }
+
+    #[test]
+    fn quotes() {
+        assert_split(
+            "select 'a;b', \"c;d\", `e;f`;\nselect 2",
+            &[(1, "select 'a;b', \"c;d\", `e;f`"), (2, "select 2")],
+        );
+        // doubled quotes and backslash escapes
+        assert_split(
+            "select 'it''s;', 'it\\'s;';\nselect \"a\"\";\";",
+            &[(1, "select 'it''s;', 'it\\'s;'"), (2, "select \"a\"\";\"")],
+        );
+        // the lines of a quoted string are counted
+        assert_split(
+            "select 'a\nb';\nselect 2",
+            &[(1, "select 'a\nb'"), (3, "select 2")],
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_split(
+            "-- a; b\nselect 1; -- c;\n/* d;\n e; */ select 2 /* ; */;\n-- only a comment;",
+            &[(2, "select 1"), (4, "select 2")],
+        );
+    }
+
+    #[test]
+    fn dollar_bodies() {
+        assert_split(
+            "create function f() as $$ select 1; select 2; $$;\nselect 3",
+            &[
+                (1, "create function f() as $$ select 1; select 2; $$"),
+                (2, "select 3"),
+            ],
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        let block = "BEGIN\n  IF x THEN\n    BEGIN\n      \
+                     SELECT CASE WHEN a THEN 1 ELSE 2 END;\n    END;\n  END IF;\nEND";
+        assert_split(
+            &format!("select 1;\n{block};\nselect 2"),
+            &[(1, "select 1"), (2, block), (9, "select 2")],
+        );
+        assert_split(
+            "select case when a then ';' end from t;\nselect 2",
+            &[
+                (1, "select case when a then ';' end from t"),
+                (2, "select 2"),
+            ],
+        );
+    }
+
+    #[test]
+    fn transactions() {
+        assert_split(
+            "BEGIN;\ninsert into t values(1);\nCOMMIT;\nbegin transaction;\nbegin work;\nBEGIN",
+            &[
+                (1, "BEGIN"),
+                (2, "insert into t values(1)"),
+                (3, "COMMIT"),
+                (4, "begin transaction"),
+                (5, "begin work"),
+                (6, "BEGIN"),
+            ],
+        );
+        // a block, closed by END
+        assert_split(
+            "BEGIN\n  select 1;\nEND;",
+            &[(1, "BEGIN\n  select 1;\nEND")],
+        );
+    }
+
+    #[test]
+    fn scripts_of_the_suites() {
+        let mut dirs = vec![std::path::PathBuf::from("sql")];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "sql") {
+                    read_statements(path.to_str().unwrap()).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("select 1;\nselect 'a;"),
+            "unterminated quote at line 2"
+        );
+        assert_eq!(error("select \"a;\n"), "unterminated quote at line 1");
+        assert_eq!(
+            error("select 1;\n\n/* a;"),
+            "unterminated block comment at line 3"
+        );
+        assert_eq!(error("select $$ a;"), "unterminated $$ body at line 1");
+        // END closes the CASE, not the BEGIN
+        assert_eq!(
+            error("select 1;\nBEGIN\n  select case when a then 1;\nEND;"),
+            "unterminated BEGIN or CASE block at line 2"
+        );
+        assert_eq!(
+            error("BEGIN\n  select 1;\nEND;\nEND;"),
+            "END without BEGIN or CASE at line 4"
+        );
+        // END IF closes no block
+        assert_eq!(
+            error("select 1;\nEND IF;\nEND;"),
+            "END without BEGIN or CASE at line 3"
+        );
+    }
+}
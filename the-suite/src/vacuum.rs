@@ -0,0 +1,281 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use clap::Parser;
+use databend_driver::Connection;
+use futures_util::StreamExt;
+use log::info;
+use tokio::task::JoinHandle;
+
+use crate::nodes::Role;
+use crate::suite::{Suite, SuiteContext};
+use crate::util::{read_statements, ConnectionExt};
+
+const SET_UP: &str = "./sql/vacuum/setup.sql";
+
+/// Vacuum Testing Script - Concurrently inserts into a table while vacuuming it with zero retention
+/// - See docs/vacuum.md for the details of the scenario
+#[derive(Parser, Clone, Debug)]
+pub struct Args {
+    /// Number of concurrent insertion threads
+    #[arg(long, default_value_t = 5)]
+    insertion_concurrency: u32,
+
+    /// Number of insert operations per thread
+    #[arg(long, default_value_t = 1000)]
+    insertion_iteration: u32,
+
+    /// Number of concurrent vacuum threads
+    #[arg(long, default_value_t = 5)]
+    vacuum_concurrency: u32,
+}
+
+#[derive(Clone)]
+pub struct VacuumSuite {
+    args: Args,
+    ctx: SuiteContext,
+    database: String,
+    success_insertions: Arc<AtomicU32>,
+    success_vacuum: Arc<AtomicU32>,
+}
+
+impl VacuumSuite {
+    async fn new_connection(&self) -> Result<Box<dyn Connection>> {
+        let conn = self.ctx.new_connection().await?;
+        conn.exec(&format!("use {}", self.database)).await?;
+        Ok(conn)
+    }
+
+    /// A connection of a worker of `role`, see [`SuiteContext::new_connection_as`]
+    async fn new_connection_as(&self, role: Role) -> Result<Box<dyn Connection>> {
+        let conn = self.ctx.new_connection_as(role).await?;
+        conn.exec(&format!("use {}", self.database)).await?;
+        Ok(conn)
+    }
+
+    async fn run_setup_script(&self) -> Result<()> {
+        info!("=====running setup script====");
+
+        let conn = self.ctx.new_connection().await?;
+        let setup_statements = read_statements(SET_UP)?;
+
+        let db_set_sqls = [
+            format!("create or replace database {}", self.database),
+            format!("use {}", self.database),
+        ];
+
+        for sql in db_set_sqls {
+            info!("executing sql: {}", sql);
+            conn.exec(&sql).await?;
+        }
+
+        for statement in &setup_statements {
+            info!("executing sql: {}", statement.sql);
+            conn.exec_statement(SET_UP, statement).await?;
+        }
+
+        info!("====setup done====");
+
+        Ok(())
+    }
+
+    async fn execute_insert(&self, batch_id: u32) -> Result<u32> {
+        let mut num_of_success = 0;
+        let iterations = self.args.insertion_iteration;
+        let step = (iterations / 100).max(1);
+        for iteration in 0..iterations {
+            if self.exec_insertion(batch_id).await? {
+                num_of_success += 1;
+            }
+
+            if (iteration + 1).is_multiple_of(step) {
+                info!(
+                    "batch {}, executed {}, progress {:.2}%",
+                    batch_id,
+                    iteration,
+                    (iteration + 1) as f32 * 100.0 / iterations as f32
+                );
+            }
+        }
+        Ok(num_of_success)
+    }
+
+    async fn exec_insertion(&self, batch_id: u32) -> Result<bool> {
+        let conn = self.new_connection_as(Role::Writer).await?;
+
+        let val = batch_id * 2;
+        let sql = format!("insert into test values({batch_id}, {val})");
+        let result = conn
+            .exec_retry(&self.ctx.retry_policy(), &self.ctx.stats(), "insert", &sql)
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                // insertion may be failed due to concurrent vacuum
+                info!("Err. insert batch : {}. {e}", batch_id);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn execute_vacuum(&self, batch_id: u32, shutdown: Arc<AtomicBool>) -> Result<u32> {
+        let mut executed = 0;
+        let mut succeed = 0;
+        while !shutdown.load(Ordering::Relaxed) {
+            if self.exec_vacuum().await.is_ok() {
+                succeed += 1;
+            }
+
+            executed += 1;
+
+            if executed % 100 == 0 {
+                info!(
+                    "vacuum batch : {}, executed {}, succeed {}",
+                    batch_id, executed, succeed
+                );
+            }
+        }
+        info!(
+            "vacuum batch : {}, executed {}, succeed {}",
+            batch_id, executed, succeed
+        );
+        Ok(succeed)
+    }
+
+    async fn exec_vacuum(&self) -> Result<()> {
+        let conn = self.new_connection_as(Role::Maintenance).await?;
+        let vacuum = conn.exec("vacuum table test RETAIN 0 HOURS");
+        let result = self.ctx.stats().timed("vacuum", vacuum).await;
+        result?;
+        Ok(())
+    }
+
+    async fn run_workload(&self) -> Result<(u32, u32)> {
+        info!("=====running test script ====");
+
+        let mut insertions: Vec<JoinHandle<Result<u32>>> = Vec::new();
+        for batch_id in 0..self.args.insertion_concurrency {
+            info!("spawning insert batch : {}", batch_id);
+            let suite = self.clone();
+            insertions.push(tokio::spawn(
+                async move { suite.execute_insert(batch_id).await },
+            ));
+        }
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let mut vacuums: Vec<JoinHandle<Result<u32>>> = Vec::new();
+        for batch_id in 0..self.args.vacuum_concurrency {
+            info!("spawning vacuum batch : {}", batch_id);
+            let suite = self.clone();
+            let shutdown = shutdown.clone();
+            vacuums.push(tokio::spawn(async move {
+                suite.execute_vacuum(batch_id, shutdown).await
+            }));
+        }
+
+        // collect the number of successfully executed insertions
+        let mut success_insertion = 0;
+        for handle in insertions {
+            if let Ok(Ok(v)) = handle.await {
+                success_insertion += v
+            }
+        }
+
+        // then we shutdown the vacuum tasks
+        shutdown.store(true, Ordering::Relaxed);
+
+        let mut success_vacuum = 0;
+        for handle in vacuums {
+            if let Ok(Ok(v)) = handle.await {
+                success_vacuum += v
+            }
+        }
+
+        Ok((success_insertion, success_vacuum))
+    }
+
+    async fn check_table_state(&self, success_insertions: u32, success_vacuum: u32) -> Result<()> {
+        info!("==========================");
+        info!("====verify table state====");
+        info!("==========================");
+        let conn = self.new_connection().await?;
+
+        info!(
+            "number of successfully executed insert-into statements : {}",
+            success_insertions
+        );
+        info!(
+            "number of successfully executed vacuum statements : {}",
+            success_vacuum
+        );
+
+        // - show the number of rows, the client may not agree with the server (e.g. communication failure)
+        {
+            let count = conn.query_count("select count() from test").await?;
+            info!(
+                "CHECK: value of successfully executed insert-into statements: client {}, server {}",
+                success_insertions, count
+            );
+        }
+
+        // - full table scan, ensure that the table data is not damaged
+        info!("CHECK: full table scanning");
+        {
+            let mut rows = conn
+                .query_iter("select * from test ignore_result")
+                .await
+                .map_err(|e| anyhow!("full table scan failed: {e}"))?;
+            while rows.next().await.is_some() {}
+        }
+
+        info!("===========================");
+        info!("======     PASSED      ====");
+        info!("===========================");
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Suite for VacuumSuite {
+    type Args = Args;
+    const NAME: &'static str = "vacuum";
+    const DESCRIPTION: &'static str =
+        "Concurrent inserts while vacuuming the table with zero retention";
+    const SETTINGS: &'static [(&'static str, &'static str)] =
+        &[("data_retention_time_in_days", "0")];
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("test_vacuum");
+        Self {
+            args,
+            ctx,
+            database,
+            success_insertions: Arc::new(AtomicU32::new(0)),
+            success_vacuum: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    async fn setup(&self) -> Result<()> {
+        self.run_setup_script().await
+    }
+
+    async fn execute(&self) -> Result<()> {
+        let (success_insertions, success_vacuum) = self.run_workload().await?;
+        self.success_insertions
+            .store(success_insertions, Ordering::Relaxed);
+        self.success_vacuum.store(success_vacuum, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<()> {
+        self.check_table_state(
+            self.success_insertions.load(Ordering::Relaxed),
+            self.success_vacuum.load(Ordering::Relaxed),
+        )
+        .await
+    }
+}
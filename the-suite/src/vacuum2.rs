@@ -1,11 +1,18 @@
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use clap::Parser;
-use databend_driver::{Client, Connection};
+use databend_driver::Connection;
 use log::info;
-use tokio::task::JoinHandle;
 use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::JoinHandle;
+
+use crate::load::{Load, LoadArgs};
+use crate::nodes::Role;
+use crate::preflight::{Requirement, FUSE_VACUUM2};
+use crate::suite::{Suite, SuiteContext};
+use crate::util::ConnectionExt;
 
 /// Vacuum2 Testing Script - Tests for table corruption with concurrent writes and vacuum operations
 /// - Tests two scenarios: simple concurrent writes and writes within explicit transactions
@@ -30,34 +37,32 @@ pub struct Args {
     /// Run scenario with explicit transactions
     #[arg(long, default_value_t = false)]
     explicit_txn: bool,
+
+    #[command(flatten)]
+    load: LoadArgs,
 }
 
 #[derive(Clone)]
 pub struct Vacuum2Suite {
     args: Args,
-    dsn: String,
+    ctx: SuiteContext,
+    database: String,
 }
 
 impl Vacuum2Suite {
-    fn new(args: Args, dsn: String) -> Self {
-        Self { args, dsn }
-    }
-
     async fn new_connection(&self) -> Result<Box<dyn Connection>> {
-        let client = Client::new(self.dsn.clone());
-        let conn = client.get_conn().await?;
-        Ok(conn)
+        self.ctx.new_connection().await
     }
 
-    async fn setup(&self) -> Result<()> {
+    async fn create_tables(&self) -> Result<()> {
         info!("===== Running setup for vacuum2 test =====");
 
         let conn = self.new_connection().await?;
 
         // Create test database and tables
         let setup_sqls = [
-            "CREATE OR REPLACE DATABASE test_vacuum2",
-            "USE test_vacuum2",
+            format!("CREATE OR REPLACE DATABASE {}", self.database),
+            format!("USE {}", self.database),
             "CREATE OR REPLACE TABLE t1 (
                 id DECIMAL(38, 0) NOT NULL,
                 a VARIANT NULL,
@@ -68,67 +73,63 @@ impl Vacuum2Suite {
                 f VARCHAR NULL,
                 g VARCHAR NULL,
                 h VARCHAR NULL
-            )",
-            // Create a random table for data generation
-            "CREATE OR REPLACE TABLE r LIKE t1 ENGINE = random",
+            )"
+            .to_owned(),
         ];
 
         for sql in setup_sqls {
             info!("Executing setup SQL: {}", sql);
-            conn.exec(sql).await?;
+            conn.exec(&sql).await?;
         }
 
         info!("===== Setup completed =====");
         Ok(())
     }
 
-    async fn execute_insert(&self, batch_id: u32) -> Result<()> {
-        let conn = self.new_connection().await?;
-
-        // Set retention period to 0 for more extreme testing
-        conn.exec("SET data_retention_time_in_days = 0").await?;
-        conn.exec("USE test_vacuum2").await?;
+    async fn execute_insert(&self, batch_id: u32, load: Load) -> Result<()> {
+        let conn = self.ctx.new_connection_as(Role::Writer).await?;
+        conn.exec(&format!("USE {}", self.database)).await?;
 
-        let sql = format!(
-            "INSERT INTO t1 SELECT * FROM r LIMIT {}",
-            self.args.insert_batch_size
-        );
+        let mut gen = self.ctx.datagen(batch_id as u64);
+        let batch_size = self.args.insert_batch_size as usize;
+        let policy = self.ctx.retry_policy();
+        let stats = self.ctx.stats();
 
         if self.args.explicit_txn {
-            // Scenario 2: Insert within explicit transaction
-            conn.exec("BEGIN").await?;
-
-            for i in 0..self.args.inserts_per_thread {
-                info!("\n===== Writer {batch_id} Iteration {i} Progress {}% =====",
-                     i * 100 / self.args.inserts_per_thread);
-
-                match conn.exec(&sql).await {
+            // Scenario 2: Insert within explicit transactions, a single one unless a duration is
+            // given. A retried transaction is run again from its BEGIN
+            let mut pacer = load.pacer(Some(1));
+            while let Some(scheduled) = pacer.next().await {
+                let inserts: Vec<_> = (0..self.args.inserts_per_thread)
+                    .map(|_| format!("INSERT INTO t1 VALUES {}", gen.vacuum_rows(batch_size)))
+                    .collect();
+                let result = policy
+                    .run_from(&stats, "commit", scheduled, || conn.exec_txn(&inserts))
+                    .await;
+                match result {
                     Ok(_) => {
-                        info!("INSERT within transaction completed successfully");
+                        info!("Transaction of writer {batch_id} committed successfully");
                     }
                     Err(e) => {
-                        info!("INSERT error within transaction: {}", e);
-                        return Ok(());
+                        info!("Transaction of writer {batch_id} error: {}", e);
                     }
                 }
             }
-
-            // Commit the transaction
-            match conn.exec("COMMIT").await {
-                Ok(_) => {
-                    info!("Transaction committed successfully");
-                }
-                Err(e) => {
-                    info!("Transaction commit error: {}", e);
-                }
-            }
         } else {
             // Scenario 1: Simple concurrent inserts
-            for i in 0..self.args.inserts_per_thread {
-                info!("\n===== Writer {batch_id} Iteration {i} Progress {}% =====",
-                     i * 100 / self.args.inserts_per_thread);
-
-                match conn.exec(&sql).await {
+            let mut pacer = load.pacer(Some(self.args.inserts_per_thread as u64));
+            while let Some(scheduled) = pacer.next().await {
+                info!(
+                    "\n===== Writer {batch_id} Iteration {} Progress {}% =====",
+                    pacer.done(),
+                    pacer.progress()
+                );
+
+                let sql = format!("INSERT INTO t1 VALUES {}", gen.vacuum_rows(batch_size));
+                let result = policy
+                    .run_from(&stats, "insert", scheduled, || conn.exec(&sql))
+                    .await;
+                match result {
                     Ok(_) => {
                         info!("INSERT completed successfully");
                     }
@@ -145,15 +146,19 @@ impl Vacuum2Suite {
     }
 
     async fn execute_vacuum(&self, vacuum_id: u32, running_flag: Arc<AtomicBool>) -> Result<()> {
-        let conn = self.new_connection().await?;
-        conn.exec("USE test_vacuum2").await?;
+        let conn = self.ctx.new_connection_as(Role::Maintenance).await?;
+        conn.exec(&format!("USE {}", self.database)).await?;
 
         info!("===== Vacuum thread {vacuum_id} starting =====");
 
-        // Keep running vacuum until the running_flag is set to false (when all inserts are done)
-        while running_flag.load(Ordering::Relaxed) {
-            conn.exec("SET data_retention_time_in_days = 0").await?;
-            match conn.exec("CALL system$fuse_vacuum2('test_vacuum2', 't1')").await {
+        // Keep running vacuum until the running_flag is set to false (when all inserts are done),
+        // or a shutdown is requested, the flag is not reset if the execution is dropped
+        let sql = format!("CALL system$fuse_vacuum2('{}', 't1')", self.database);
+        let shutdown = self.ctx.shutdown();
+        while running_flag.load(Ordering::Relaxed) && !shutdown.is_requested() {
+            let vacuum = conn.exec(&sql);
+            let result = self.ctx.stats().timed("vacuum2", vacuum).await;
+            match result {
                 Ok(_) => {
                     info!("VACUUM iteration completed successfully");
                 }
@@ -169,7 +174,7 @@ impl Vacuum2Suite {
 
     async fn check_table_health(&self) -> Result<bool> {
         let conn = self.new_connection().await?;
-        conn.exec("USE test_vacuum2").await?;
+        conn.exec(&format!("USE {}", self.database)).await?;
         let sql = "SELECT * FROM t1 ignore_result";
 
         match conn.exec(sql).await {
@@ -186,68 +191,261 @@ impl Vacuum2Suite {
 
     async fn run_concurrent_inserts(&self) -> Result<Vec<JoinHandle<Result<()>>>> {
         let mut handles = Vec::new();
+        let load = self.args.load.start(&self.ctx.shutdown());
 
         for i in 0..self.args.writers {
             let self_clone = Arc::new(self.clone());
-            let handle = tokio::spawn(async move { self_clone.execute_insert(i).await });
+            let load = load.clone();
+            let handle = tokio::spawn(async move { self_clone.execute_insert(i, load).await });
             handles.push(handle);
         }
 
         Ok(handles)
     }
 
-    async fn run_concurrent_vacuums(&self, running_flag: Arc<AtomicBool>) -> Result<Vec<JoinHandle<Result<()>>>> {
+    async fn run_concurrent_vacuums(
+        &self,
+        running_flag: Arc<AtomicBool>,
+    ) -> Result<Vec<JoinHandle<Result<()>>>> {
         let mut handles = Vec::new();
 
         for i in 0..self.args.vacuumers {
             let self_clone = Arc::new(self.clone());
             let running_flag_clone = running_flag.clone();
-            let handle = tokio::spawn(async move { self_clone.execute_vacuum(i, running_flag_clone).await });
+            let handle =
+                tokio::spawn(async move { self_clone.execute_vacuum(i, running_flag_clone).await });
             handles.push(handle);
         }
 
         Ok(handles)
     }
 
+    fn scenario_name(&self) -> &'static str {
+        if self.args.explicit_txn {
+            "explicit transaction"
+        } else {
+            "simple concurrent writes"
+        }
+    }
+
     async fn wait_for_completion(&self, handles: Vec<JoinHandle<Result<()>>>) -> Result<()> {
         for handle in handles {
             handle.await??;
         }
         Ok(())
     }
+}
+
+#[async_trait]
+impl Suite for Vacuum2Suite {
+    type Args = Args;
+    const NAME: &'static str = "vacuum2";
+    const DESCRIPTION: &'static str =
+        "Concurrent writes (optionally in explicit transactions) while vacuum2 purges the table";
+    // zero retention for more extreme testing
+    const SETTINGS: &'static [(&'static str, &'static str)] =
+        &[("data_retention_time_in_days", "0")];
+    const REQUIREMENTS: &'static [Requirement] = &[Requirement::Feature(&FUSE_VACUUM2)];
+    const CRASH_RECOVERY: bool = true;
+
+    fn new(args: Args, ctx: SuiteContext) -> Self {
+        let database = ctx.database("test_vacuum2");
+        Self {
+            args,
+            ctx,
+            database,
+        }
+    }
 
-    pub async fn run(args: Args, dsn: String) -> Result<()> {
-        let explicit_txn = args.explicit_txn;
-        let suite = Self::new(args, dsn);
-        suite.setup().await?;
+    async fn setup(&self) -> Result<()> {
+        self.create_tables().await
+    }
 
+    async fn execute(&self) -> Result<()> {
         // Create a flag to signal when inserts are complete
         let running_flag = Arc::new(AtomicBool::new(true));
 
         // Run concurrent writers and vacuumers
-        let scenario_name = if explicit_txn { "explicit transaction" } else { "simple concurrent writes" };
-        info!("===== Running vacuum2 test with {} scenario =====", scenario_name);
+        let scenario_name = self.scenario_name();
+        info!(
+            "===== Running vacuum2 test with {} scenario =====",
+            scenario_name
+        );
 
-        let writer_handles = suite.run_concurrent_inserts().await?;
-        let vacuum_handles = suite.run_concurrent_vacuums(running_flag.clone()).await?;
+        let writer_handles = self.run_concurrent_inserts().await?;
+        let vacuum_handles = self.run_concurrent_vacuums(running_flag.clone()).await?;
 
         // Wait for all writers to complete
-        suite.wait_for_completion(writer_handles).await?;
+        self.wait_for_completion(writer_handles).await?;
 
         // Signal vacuum threads to stop and wait for them to complete
         running_flag.store(false, Ordering::Relaxed);
-        suite.wait_for_completion(vacuum_handles).await?;
+        self.wait_for_completion(vacuum_handles).await
+    }
 
+    async fn verify(&self) -> Result<()> {
         // Check table health
-        if !suite.check_table_health().await? {
+        if !self.check_table_health().await? {
             return Err(anyhow!("Table health check failed. Test terminated."));
         }
 
-        info!("===== Vacuum2 test with {} scenario completed successfully =====", scenario_name);
+        info!(
+            "===== Vacuum2 test with {} scenario completed successfully =====",
+            self.scenario_name()
+        );
         Ok(())
     }
+
+    async fn committed_rows(&self) -> Result<u64> {
+        if !self.check_table_health().await? {
+            return Err(anyhow!("Table health check failed"));
+        }
+        let conn = self.new_connection().await?;
+        let sql = format!("SELECT count(*) FROM {}.t1", self.database);
+        let rows: Vec<(u64,)> = conn.exec_query(&sql).await?;
+        Ok(rows.first().map_or(0, |row| row.0))
+    }
 }
 
-pub async fn run(args: Args, dsn: String) -> Result<()> {
-    Vacuum2Suite::run(args, dsn).await
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::errors::ErrorCategory;
+    use crate::mock::{MockServer, Reply};
+    use crate::retry::RetryPolicy;
+    use crate::suite::{run_suite, SuiteEntry};
+
+    fn args(explicit_txn: bool) -> Args {
+        Args {
+            writers: 3,
+            vacuumers: 2,
+            inserts_per_thread: 4,
+            insert_batch_size: 10,
+            explicit_txn,
+            load: LoadArgs::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn vacuums_until_writers_are_done() {
+        let mock = MockServer::new();
+        mock.when("fuse_vacuum2", Reply::ok().delay(Duration::from_millis(5)));
+        let ctx = mock.context();
+        run_suite(Vacuum2Suite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        assert_eq!(mock.count("INSERT INTO t1 VALUES"), 12);
+        assert_eq!(ctx.stats().snapshot()["insert"].success, 12);
+        let latencies = ctx.stats().latencies(Duration::from_secs(3600));
+        assert_eq!(latencies["insert"].count, 12);
+        // over the execute phase, not the hour given for the whole run
+        assert!(latencies["insert"].ops_per_sec > 12.0);
+        assert!(latencies["vacuum2"].p50_us >= 5_000);
+        // no vacuum is issued once the health check starts
+        let statements = mock.statements();
+        let check = statements
+            .iter()
+            .position(|sql| sql.contains("ignore_result"))
+            .unwrap();
+        assert!(!statements[check..]
+            .iter()
+            .any(|sql| sql.contains("fuse_vacuum2")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_inserts_do_not_fail_the_suite() {
+        let mock = MockServer::new();
+        mock.when("INSERT INTO t1", Reply::error(4001, "conflict").times(5));
+        let ctx = mock.context();
+        run_suite(Vacuum2Suite::new(args(false), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        let inserts = &ctx.stats().snapshot()["insert"];
+        assert_eq!((inserts.success, inserts.failed), (7, 5));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_insert_rolls_back_the_transaction() {
+        let mock = MockServer::new();
+        mock.when("INSERT INTO t1", Reply::error(4001, "conflict").times(1));
+        let ctx = mock.context();
+        run_suite(Vacuum2Suite::new(args(true), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        // the writer of the failed insert neither inserts nor commits any more
+        assert_eq!(mock.count("INSERT INTO t1"), 9);
+        assert_eq!(mock.count("COMMIT"), 2);
+        assert_eq!(mock.count("ROLLBACK"), 1);
+        let commits = &ctx.stats().snapshot()["commit"];
+        assert_eq!((commits.success, commits.failed), (2, 1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn retried_transaction_is_run_again() {
+        let mock = MockServer::new();
+        mock.when("INSERT INTO t1", Reply::error(4001, "conflict").times(1));
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let ctx = mock.context().with_retry_policy(policy);
+        run_suite(Vacuum2Suite::new(args(true), ctx.clone()), &ctx)
+            .await
+            .unwrap();
+
+        // the failed transaction is run again as a whole, all of them commit
+        assert_eq!(mock.count("INSERT INTO t1"), 13);
+        assert_eq!(mock.count("COMMIT"), 3);
+        let commits = &ctx.stats().snapshot()["commit"];
+        assert_eq!(
+            (commits.success, commits.failed, commits.attempts),
+            (3, 0, 4)
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn corrupted_table_fails_the_suite() {
+        let mock = MockServer::new();
+        mock.when("ignore_result", Reply::error(1001, "storage error"));
+        let ctx = mock.context();
+        let result = run_suite(Vacuum2Suite::new(args(false), ctx.clone()), &ctx).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unexpected_insert_error_fails_the_suite() {
+        let entry = SuiteEntry::of::<Vacuum2Suite>();
+        let matches = entry
+            .command()
+            .try_get_matches_from(["vacuum2", "--writers", "2", "--inserts-per-thread", "3"])
+            .unwrap();
+        let run = |ctx: SuiteContext| entry.start(&matches, ctx).unwrap();
+
+        let mock = MockServer::new();
+        mock.when(
+            "INSERT INTO t1",
+            Reply::error(3001, "file not found").times(1),
+        );
+        let ctx = mock.context();
+        let error = run(ctx.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("file not found"));
+        assert_eq!(ctx.stats().errors()[&ErrorCategory::Corruption], 1);
+
+        let mock = MockServer::new();
+        mock.when(
+            "INSERT INTO t1",
+            Reply::error(3001, "file not found").times(1),
+        );
+        let ctx = mock
+            .context()
+            .with_allowed_errors(vec![ErrorCategory::Corruption]);
+        run(ctx).await.unwrap();
+    }
 }